@@ -16,6 +16,7 @@ use nyx::{
     protocol::{ClientId, Clientbound, ClientboundBundle, Serverbound, Tick, TPS},
     task::Proficiencies,
 };
+use tecs::utils::Time;
 
 const FORCED_LATENCY: Duration = Duration::from_millis(0);
 
@@ -142,8 +143,13 @@ fn main() -> Result<()> {
     let recipes = data::recipes();
     let nodes = data::nodes::get();
 
+    let mut time = Time::new(TPS);
+    let mut last = Instant::now();
+
     loop {
         let start = Instant::now();
+        time.advance(start - last);
+        last = start;
 
         while let Ok((addr, message)) = rx.try_recv() {
             if let Serverbound::AuthRequest = message {
@@ -303,8 +309,12 @@ fn main() -> Result<()> {
             }
         }
 
-        tick.0 += 1;
-        flush_tx.send(tick).unwrap();
-        std::thread::sleep(Duration::from_secs_f32(1.0 / TPS) - start.elapsed())
+        // The same fixed steps as the client's `Time`, ticks slept past are
+        // caught up on rather than skipped
+        for _ in 0..time.steps() {
+            tick.0 += 1;
+            flush_tx.send(tick).unwrap();
+        }
+        std::thread::sleep(time.step.saturating_sub(start.elapsed()))
     }
 }
@@ -0,0 +1,97 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use tecs::prelude::*;
+
+#[derive(Clone, Copy)]
+struct Position(f32);
+
+#[derive(Clone, Copy)]
+struct Velocity(f32);
+
+#[derive(Archetype, Clone)]
+struct Particle {
+    position: Position,
+    velocity: Velocity,
+}
+
+#[derive(Archetype, Clone)]
+struct Marker {
+    position: Position,
+}
+
+fn world(n: usize) -> tecs::World<()> {
+    let world = tecs::World::new()
+        .register_unsaved::<Particle>()
+        .register_unsaved::<Marker>();
+    (0..n).for_each(|i| {
+        if i % 2 == 0 {
+            world.spawn(Particle {
+                position: Position(i as f32),
+                velocity: Velocity(1.0),
+            });
+        } else {
+            world.spawn(Marker {
+                position: Position(i as f32),
+            });
+        }
+    });
+    world
+}
+
+fn query_entities(c: &mut Criterion) {
+    let mut group = c.benchmark_group("query_entities");
+    for n in [1_000, 10_000, 100_000] {
+        let world = world(n);
+        group.bench_with_input(BenchmarkId::from_parameter(n), &world, |b, world| {
+            b.iter(|| {
+                let (entities, positions) = world.query::<(EntityId, &Position)>();
+                entities
+                    .iter()
+                    .zip(positions.iter())
+                    .for_each(|(entity, position)| {
+                        black_box((entity, position.0));
+                    })
+            })
+        });
+    }
+    group.finish();
+}
+
+fn query_velocities(c: &mut Criterion) {
+    let world = world(100_000);
+    c.bench_function("query_velocities_100k", |b| {
+        b.iter(|| {
+            let (entities, velocities) = world.query::<(EntityId, &Velocity)>();
+            entities
+                .iter()
+                .zip(velocities.iter())
+                .for_each(|(entity, velocity)| {
+                    black_box((entity, velocity.0));
+                })
+        })
+    });
+}
+
+fn despawn(c: &mut Criterion) {
+    c.bench_function("despawn_100k", |b| {
+        b.iter_batched(
+            || {
+                let world = world(100_000);
+                let entities = {
+                    let (entities, _) = world.query::<(EntityId, Is<Particle>)>();
+                    entities.iter().copied().collect::<Vec<_>>()
+                };
+                (world, entities)
+            },
+            |(world, entities)| {
+                entities.into_iter().for_each(|entity| {
+                    world.despawn(entity).unwrap();
+                });
+                world
+            },
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+criterion_group!(benches, query_entities, query_velocities, despawn);
+criterion_main!(benches);
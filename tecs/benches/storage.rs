@@ -0,0 +1,98 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use tecs::prelude::*;
+
+#[derive(Clone, Copy)]
+struct Position(f32);
+
+/// Toggled on and off, stored in tables or sparsely depending on the world
+#[derive(Clone, Copy)]
+struct Targeted;
+
+#[derive(Archetype, Clone)]
+struct Thing {
+    position: Position,
+}
+
+fn world(n: usize, sparse: bool) -> (tecs::World<()>, Vec<EntityId>) {
+    let mut world = tecs::World::new().register_unsaved::<Thing>();
+    if sparse {
+        world = world.with_sparse::<Targeted>();
+    }
+    let entities = (0..n)
+        .map(|i| {
+            world.spawn(Thing {
+                position: Position(i as f32),
+            })
+        })
+        .collect();
+    (world, entities)
+}
+
+fn storage(sparse: bool) -> &'static str {
+    if sparse {
+        "sparse"
+    } else {
+        "table"
+    }
+}
+
+fn insert_remove(c: &mut Criterion) {
+    let mut group = c.benchmark_group("insert_remove_10k");
+    for sparse in [false, true] {
+        group.bench_function(storage(sparse), |b| {
+            b.iter_batched(
+                || world(10_000, sparse),
+                |(world, entities)| {
+                    entities.iter().for_each(|entity| {
+                        world.insert_component(*entity, Targeted).unwrap();
+                    });
+                    entities.iter().for_each(|entity| {
+                        world.remove_component::<Targeted>(*entity).unwrap();
+                    });
+                    world
+                },
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn iterate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("iterate_targeted_100k");
+    for sparse in [false, true] {
+        let (world, entities) = world(100_000, sparse);
+        entities.iter().step_by(10).for_each(|entity| {
+            world.insert_component(*entity, Targeted).unwrap();
+        });
+        group.bench_with_input(
+            BenchmarkId::from_parameter(storage(sparse)),
+            &world,
+            |b, world| {
+                if sparse {
+                    b.iter(|| {
+                        let (positions, targeted) = world.query::<(&Position, Sparse<&Targeted>)>();
+                        positions
+                            .iter()
+                            .zip(targeted.iter())
+                            .filter(|(_, targeted)| targeted.is_some())
+                            .for_each(|(position, _)| {
+                                black_box(position.0);
+                            })
+                    })
+                } else {
+                    b.iter(|| {
+                        let (positions, _) = world.query::<(&Position, With<Targeted>)>();
+                        positions.iter().for_each(|position| {
+                            black_box(position.0);
+                        })
+                    })
+                }
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, insert_remove, iterate);
+criterion_main!(benches);
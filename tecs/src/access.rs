@@ -0,0 +1,111 @@
+use std::any::{type_name, TypeId};
+
+/// The components and resources something reads and writes, used to catch
+/// aliased mutable borrows before the `RefCell`s do. Components and
+/// resources are kept apart since a resource can have the same type as a
+/// component without sharing its borrow.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    components: Borrows,
+    resources: Borrows,
+}
+
+#[derive(Clone, Debug, Default)]
+struct Borrows {
+    reads: Vec<(TypeId, &'static str)>,
+    writes: Vec<(TypeId, &'static str)>,
+}
+
+impl Borrows {
+    fn read<T: 'static>(&mut self) {
+        self.reads.push((TypeId::of::<T>(), type_name::<T>()))
+    }
+
+    fn write<T: 'static>(&mut self) {
+        self.writes.push((TypeId::of::<T>(), type_name::<T>()))
+    }
+
+    fn conflicts<'a>(&'a self, other: &'a Borrows) -> impl Iterator<Item = &'static str> + 'a {
+        self.writes
+            .iter()
+            .filter(|(ty, _)| {
+                other
+                    .reads
+                    .iter()
+                    .chain(&other.writes)
+                    .any(|(other, _)| other == ty)
+            })
+            .chain(
+                other
+                    .writes
+                    .iter()
+                    .filter(|(ty, _)| self.reads.iter().any(|(other, _)| other == ty)),
+            )
+            .map(|(_, name)| *name)
+    }
+
+    fn aliased(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.writes
+            .iter()
+            .enumerate()
+            .filter(|(i, (ty, _))| {
+                self.reads.iter().any(|(other, _)| other == ty)
+                    || self.writes[i + 1..].iter().any(|(other, _)| other == ty)
+            })
+            .map(|(_, (_, name))| *name)
+    }
+
+    fn extend(&mut self, other: &Borrows) {
+        self.reads.extend_from_slice(&other.reads);
+        self.writes.extend_from_slice(&other.writes);
+    }
+}
+
+impl Access {
+    /// Reads the component `T`
+    pub fn read<T: 'static>(&mut self) {
+        self.components.read::<T>()
+    }
+
+    /// Writes the component `T`
+    pub fn write<T: 'static>(&mut self) {
+        self.components.write::<T>()
+    }
+
+    pub fn read_resource<T: 'static>(&mut self) {
+        self.resources.read::<T>()
+    }
+
+    pub fn write_resource<T: 'static>(&mut self) {
+        self.resources.write::<T>()
+    }
+
+    /// Names of the types written by one side and read or written by the other
+    pub fn conflicts(&self, other: &Access) -> Vec<&'static str> {
+        let mut conflicts = self
+            .components
+            .conflicts(&other.components)
+            .chain(self.resources.conflicts(&other.resources))
+            .collect::<Vec<_>>();
+        conflicts.sort();
+        conflicts.dedup();
+        conflicts
+    }
+
+    /// Names of the types this both writes and reads or writes more than once
+    pub fn aliased(&self) -> Vec<&'static str> {
+        let mut aliased = self
+            .components
+            .aliased()
+            .chain(self.resources.aliased())
+            .collect::<Vec<_>>();
+        aliased.sort();
+        aliased.dedup();
+        aliased
+    }
+
+    pub fn extend(&mut self, other: &Access) {
+        self.components.extend(&other.components);
+        self.resources.extend(&other.resources);
+    }
+}
@@ -0,0 +1,367 @@
+use std::{
+    any::TypeId,
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    sync::{Cell, Ref, RefMut},
+    Fetch, Join, Query, QueryOne, RowIndex, SparseSets, Table,
+};
+
+/// The world's change tick when the running system last ran and now, rows
+/// count as changed when they were changed after `last_run`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Ticks {
+    pub last_run: u32,
+    pub this_run: u32,
+}
+
+/// When a row of a column was added and last mutably accessed
+#[derive(Debug)]
+pub struct ComponentTicks {
+    pub added: Cell<u32>,
+    pub changed: Cell<u32>,
+}
+
+impl ComponentTicks {
+    pub(crate) fn new(tick: u32) -> Self {
+        Self {
+            added: Cell::new(tick),
+            changed: Cell::new(tick),
+        }
+    }
+}
+
+/// Mutable reference to a component yielded by [`Join::iter_mut`], the row is
+/// only marked changed when dereferenced mutably.
+pub struct Mut<'a, T> {
+    pub(crate) value: &'a mut T,
+    pub(crate) ticks: &'a ComponentTicks,
+    pub(crate) tick: u32,
+}
+
+impl<T> Deref for Mut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        self.value
+    }
+}
+
+impl<T> DerefMut for Mut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed.set(self.tick);
+        self.value
+    }
+}
+
+/// Borrow of a single component, like [`Mut`] it is only marked changed
+/// when dereferenced mutably.
+pub struct ComponentMut<'a, T> {
+    pub(crate) value: RefMut<'a, T>,
+    pub(crate) ticks: Ref<'a, ComponentTicks>,
+    pub(crate) tick: u32,
+}
+
+impl<T> Deref for ComponentMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for ComponentMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.ticks.changed.set(self.tick);
+        &mut self.value
+    }
+}
+
+/// Row by row results of a [`Changed`] or [`Added`] filter
+pub struct TickFilter<'a> {
+    columns: Vec<Ref<'a, [ComponentTicks]>>,
+    last_run: u32,
+    added: bool,
+}
+
+impl<'a> TickFilter<'a> {
+    fn new<T: 'static>(tables: &[(TypeId, &'a Table)], ticks: Ticks, added: bool) -> Self {
+        Self {
+            columns: tables
+                .iter()
+                .map(|(_, table)| table.ticks::<T>().unwrap())
+                .collect(),
+            last_run: ticks.last_run,
+            added,
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        self.columns
+            .iter()
+            .flat_map(|column| column.iter())
+            .map(|ticks| matches(ticks, self.last_run, self.added))
+    }
+}
+
+fn matches(ticks: &ComponentTicks, last_run: u32, added: bool) -> bool {
+    let tick = if added { &ticks.added } else { &ticks.changed };
+    tick.get() > last_run
+}
+
+pub struct TickFetch<'b> {
+    slices: Vec<&'b [ComponentTicks]>,
+    last_run: u32,
+    added: bool,
+}
+
+impl Fetch for TickFetch<'_> {
+    type Item = ();
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.slices.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.slices[table].len())
+    }
+
+    fn matches(&self, table: usize, row: usize) -> bool {
+        matches(&self.slices[table][row], self.last_run, self.added)
+    }
+
+    unsafe fn get(&mut self, _: usize, _: usize) -> Self::Item {}
+}
+
+impl Join for TickFilter<'_> {
+    type Fetch<'b>
+        = TickFetch<'b>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        TickFetch {
+            slices: self.columns.iter().map(|column| &**column).collect(),
+            last_run: self.last_run,
+            added: self.added,
+        }
+    }
+}
+
+/// Only matches rows whose `T` has been mutably accessed since the system
+/// last ran, including rows that were added.
+pub struct Changed<T>(PhantomData<T>);
+
+/// Only matches rows whose `T` has been added since the system last ran
+pub struct Added<T>(PhantomData<T>);
+
+macro_rules! impl_tick_filter {
+    ($filter:ident, $added:literal) => {
+        impl<E, T: 'static> Query<E> for $filter<T> {
+            type Output<'a> = TickFilter<'a>;
+
+            fn filter(table: &(TypeId, &Table)) -> bool {
+                table.1.has_column::<T>()
+            }
+
+            fn data<'a>(
+                tables: &[(TypeId, &'a Table)],
+                _: &'a SparseSets,
+                ticks: Ticks,
+            ) -> Self::Output<'a> {
+                TickFilter::new::<T>(tables, ticks, $added)
+            }
+        }
+
+        impl<E, T: 'static> QueryOne<E> for $filter<T> {
+            type Output<'a> = bool;
+
+            fn filter(table: &(TypeId, &Table)) -> bool {
+                table.1.has_column::<T>()
+            }
+
+            fn matches(
+                (_, table): (TypeId, &Table),
+                row: RowIndex,
+                _: &SparseSets,
+                ticks: Ticks,
+            ) -> bool {
+                matches(
+                    &table.ticks::<T>().unwrap()[row.0 as usize],
+                    ticks.last_run,
+                    $added,
+                )
+            }
+
+            fn data<'a>(
+                table: (TypeId, &'a Table),
+                row: RowIndex,
+                sparse: &'a SparseSets,
+                ticks: Ticks,
+            ) -> Self::Output<'a> {
+                <Self as QueryOne<E>>::matches(table, row, sparse, ticks)
+            }
+        }
+    };
+}
+
+impl_tick_filter!(Changed, false);
+impl_tick_filter!(Added, true);
+
+#[cfg(test)]
+mod tests {
+    use crate::{prelude::*, resource_changed, sync::RefCell, Added, Changed, World};
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        value: u32,
+        name: String,
+    }
+
+    type Seen = RefCell<Vec<(u32, bool, bool)>>;
+
+    fn record(world: &World<()>) {
+        let (values, changed, added) = world.query::<(&u32, Changed<u32>, Added<u32>)>();
+        world.get::<Seen>().unwrap().borrow_mut().extend(
+            values
+                .iter()
+                .zip(changed.iter().zip(added.iter()))
+                .map(|(value, (changed, added))| (*value, changed, added)),
+        );
+    }
+
+    fn seen(world: &World<()>) -> Vec<(u32, bool, bool)> {
+        world.tick();
+        let mut seen = world
+            .get::<Seen>()
+            .unwrap()
+            .borrow_mut()
+            .drain(..)
+            .collect::<Vec<_>>();
+        seen.sort();
+        seen
+    }
+
+    fn thing(value: u32) -> Thing {
+        Thing {
+            value,
+            name: value.to_string(),
+        }
+    }
+
+    fn world() -> World<()> {
+        World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_resource(Seen::default())
+            .with_ticker(record)
+    }
+
+    #[test]
+    pub fn test_columns_mut() {
+        let world = world();
+        world.spawn(thing(0));
+        world.spawn(thing(1));
+        assert_eq!(seen(&world), vec![(0, true, true), (1, true, true)]);
+        assert_eq!(seen(&world), vec![(0, false, false), (1, false, false)]);
+
+        let values = world.query::<&mut u32>();
+        assert_eq!(values.iter().count(), 2);
+        drop(values);
+        assert_eq!(seen(&world), vec![(0, false, false), (1, false, false)]);
+
+        *world.query::<&mut u32>().get_mut(1).unwrap() += 10;
+        assert_eq!(seen(&world), vec![(0, false, false), (11, true, false)]);
+
+        world.query::<&mut u32>().for_each(|_| ());
+        assert_eq!(seen(&world), vec![(0, false, false), (11, false, false)]);
+
+        world.query::<&mut u32>().for_each(|mut value| {
+            if *value == 0 {
+                *value += 1
+            }
+        });
+        assert_eq!(seen(&world), vec![(1, true, false), (11, false, false)]);
+
+        let mut query = world.query::<(&mut u32, &String)>();
+        query
+            .iter_mut()
+            .filter(|(_, name)| name.as_str() == "1")
+            .for_each(|(mut value, _)| *value += 1);
+        drop(query);
+        assert_eq!(seen(&world), vec![(1, false, false), (12, true, false)]);
+    }
+
+    #[test]
+    pub fn test_get_component_mut() {
+        let world = world();
+        let a = world.spawn(thing(0));
+        let b = world.spawn(thing(1));
+        seen(&world);
+
+        assert_eq!(*world.get_component_mut::<u32>(a).unwrap(), 0);
+        *world.get_component_mut::<String>(b).unwrap() = String::from("b");
+        assert_eq!(seen(&world), vec![(0, false, false), (1, false, false)]);
+
+        *world.get_component_mut::<u32>(b).unwrap() = 2;
+        assert_eq!(seen(&world), vec![(0, false, false), (2, true, false)]);
+
+        world.insert_component(a, 3_u32).unwrap();
+        world.commands().spawn(thing(4));
+        world.apply_commands();
+        assert_eq!(
+            seen(&world),
+            vec![(2, false, false), (3, true, false), (4, true, true)]
+        );
+
+        world.insert_component(b, 0.5_f32).unwrap();
+        assert_eq!(
+            seen(&world),
+            vec![(2, false, false), (3, false, false), (4, false, false)]
+        );
+    }
+
+    #[test]
+    pub fn test_join_filter() {
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_resource(Vec::<EntityId>::new())
+            .with_ticker(|world| {
+                let mut query = world.query::<(EntityId, Changed<String>)>();
+                let changed = query.iter_mut().map(|(id, _)| *id).collect();
+                *world.get_mut::<Vec<EntityId>>().unwrap() = changed;
+            });
+        let a = world.spawn(thing(0));
+        let b = world.spawn(thing(1));
+        world.tick();
+        assert_eq!(*world.get::<Vec<EntityId>>().unwrap(), vec![a, b]);
+
+        world.get_component_mut::<String>(b).unwrap().push('!');
+        world.tick();
+        assert_eq!(*world.get::<Vec<EntityId>>().unwrap(), vec![b]);
+
+        world.tick();
+        assert!(world.get::<Vec<EntityId>>().unwrap().is_empty());
+    }
+
+    #[test]
+    pub fn test_resource() {
+        let world = World::<()>::new()
+            .with_resource(0_u32)
+            .with_resource(0_usize)
+            .with_ticker(|world| *world.get_mut::<usize>().unwrap() += 1)
+            .run_if(resource_changed::<(), u32>());
+
+        world.tick();
+        assert_eq!(*world.get::<usize>().unwrap(), 1);
+        world.tick();
+        assert_eq!(*world.get::<usize>().unwrap(), 1);
+
+        *world.get_mut::<u32>().unwrap() += 1;
+        assert!(world.resource_changed::<u32>());
+        world.tick();
+        world.tick();
+        assert_eq!(*world.get::<usize>().unwrap(), 2);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{Archetype, EntityId, MaybeSync, World};
+
+pub(crate) trait CommandFn<E>: FnOnce(&World<E>) + MaybeSync {}
+impl<E, T: FnOnce(&World<E>) + MaybeSync> CommandFn<E> for T {}
+
+pub(crate) type Command<E> = Box<dyn CommandFn<E>>;
+
+/// Queues structural changes to the world so they can be made while columns
+/// are borrowed, they are applied in order at the end of [`World::tick`] or
+/// by [`World::apply_commands`].
+pub struct Commands<'a, E> {
+    world: &'a World<E>,
+}
+
+impl<'a, E> Commands<'a, E> {
+    pub(crate) fn new(world: &'a World<E>) -> Self {
+        Self { world }
+    }
+
+    pub(crate) fn push<F: FnOnce(&World<E>) + MaybeSync + 'static>(&self, command: F) {
+        self.world.commands.lock().push(Box::new(command))
+    }
+
+    /// Reserves an id for the entity straight away, it isn't alive until the
+    /// commands are applied.
+    pub fn spawn<T: Archetype + MaybeSync>(&self, entity: T) -> EntityId {
+        let id = self.world.entities.lock().reserve();
+        self.push(move |world| world.spawn_at(id, entity));
+        id
+    }
+
+    pub fn despawn(&self, id: EntityId) {
+        self.push(move |world| {
+            let _ = world.despawn(id);
+        })
+    }
+
+    pub fn insert<T: MaybeSync + 'static>(&self, id: EntityId, component: T) {
+        self.push(move |world| {
+            let _ = world.insert_component(id, component);
+        })
+    }
+
+    pub fn remove<T: MaybeSync + 'static>(&self, id: EntityId) {
+        self.push(move |world| {
+            let _ = world.remove_component::<T>(id);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{EntityId, Is, World};
+    use tecs_derive::Archetype;
+
+    #[derive(Archetype, Clone, Debug, PartialEq)]
+    struct Thing {
+        value: u32,
+    }
+
+    #[test]
+    pub fn test_reserve() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let a = world.spawn(Thing { value: 0 });
+        let b = world.commands().spawn(Thing { value: 1 });
+        let c = world.commands().spawn(Thing { value: 2 });
+        assert_ne!(a, b);
+        assert_ne!(b, c);
+        assert!(!world.is_alive(b));
+        assert!(world.get_component::<u32>(b).is_none());
+
+        world.apply_commands();
+        assert!(world.is_alive(b));
+        assert_eq!(*world.get_component::<u32>(b).unwrap(), 1);
+        assert_eq!(*world.get_component::<u32>(c).unwrap(), 2);
+    }
+
+    #[test]
+    pub fn test_ordering() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let a = world.spawn(Thing { value: 0 });
+
+        let commands = world.commands();
+        let b = commands.spawn(Thing { value: 1 });
+        commands.insert(b, String::from("b"));
+        commands.remove::<String>(b);
+        commands.insert(b, String::from("B"));
+        commands.despawn(a);
+        commands.insert(a, String::from("a"));
+        world.apply_commands();
+
+        assert!(!world.is_alive(a));
+        assert_eq!(*world.get_component::<String>(b).unwrap(), "B");
+    }
+
+    #[test]
+    pub fn test_despawn_reserved() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let a = world.commands().spawn(Thing { value: 7 });
+        assert!(world.despawn(a).is_err());
+        world.apply_commands();
+        assert!(world.is_alive(a));
+
+        let b = world.spawn(Thing { value: 8 });
+        assert_ne!(a.index(), b.index());
+        // No row is left behind for an id that isn't alive
+        let mut ids = world
+            .query::<EntityId>()
+            .iter()
+            .copied()
+            .collect::<Vec<_>>();
+        ids.sort_by_key(|id| id.index());
+        assert_eq!(ids, vec![a, b]);
+    }
+
+    #[test]
+    pub fn test_while_borrowed() {
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_ticker(|world| {
+                let (mut values, entities, _) = world.query::<(&mut u32, EntityId, Is<Thing>)>();
+                values.for_each(|mut value| *value += 1);
+                entities.iter().for_each(|id| world.commands().despawn(*id));
+                world.commands().spawn(Thing { value: 0 });
+            });
+        world.spawn(Thing { value: 0 });
+
+        world.tick();
+        world.tick();
+        let values = world.query::<&u32>();
+        assert_eq!(values.iter().collect::<Vec<_>>(), vec![&0]);
+    }
+}
@@ -0,0 +1,223 @@
+use std::fmt::Display;
+
+use crate::{RowIndex, TableId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+impl EntityId {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntityError {
+    Dead(EntityId),
+    MissingComponent(EntityId),
+    /// The entity would become its own ancestor
+    Cycle(EntityId),
+}
+
+impl Display for EntityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Dead(id) => write!(f, "Entity {id:?} is not alive"),
+            Self::MissingComponent(id) => write!(f, "Entity {id:?} is missing the component"),
+            Self::Cycle(id) => write!(f, "Entity {id:?} can't be parented to its descendant"),
+        }
+    }
+}
+
+impl std::error::Error for EntityError {}
+
+struct Slot {
+    generation: u32,
+    /// Highest generation the slot has had, freeing moves past it so ids
+    /// brought back by [`Entities::reclaim`] can't make later ones collide
+    latest: u32,
+    location: Option<(TableId, RowIndex)>,
+    /// Whether the slot can be reserved, reclaimed slots stay in the free
+    /// list and are skipped there
+    free: bool,
+}
+
+impl Slot {
+    fn new() -> Self {
+        Self {
+            generation: 0,
+            latest: 0,
+            location: None,
+            free: false,
+        }
+    }
+
+    fn next_generation(&mut self) {
+        self.latest = self.latest.wrapping_add(1);
+        self.generation = self.latest;
+        self.free = true;
+    }
+}
+
+/// Dense slot array of entity locations, an id is only valid while its
+/// generation matches the generation of its slot.
+#[derive(Default)]
+pub(crate) struct Entities {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+}
+
+impl Entities {
+    /// Reserves an id whose slot has no location yet, the entity only
+    /// becomes alive once it is given one with [`Entities::set`].
+    pub fn reserve(&mut self) -> EntityId {
+        while let Some(index) = self.free.pop() {
+            let slot = &mut self.slots[index as usize];
+            if slot.free {
+                slot.free = false;
+                return EntityId {
+                    index,
+                    generation: slot.generation,
+                };
+            }
+        }
+
+        self.slots.push(Slot::new());
+        EntityId {
+            index: self.slots.len() as u32 - 1,
+            generation: 0,
+        }
+    }
+
+    /// Reserves a specific id, used to bring back entities that were freed.
+    /// Returns false if the slot is taken by a live or reserved entity.
+    pub fn reclaim(&mut self, id: EntityId) -> bool {
+        while self.slots.len() <= id.index as usize {
+            self.free.push(self.slots.len() as u32);
+            self.slots.push(Slot {
+                free: true,
+                ..Slot::new()
+            });
+        }
+
+        let slot = &mut self.slots[id.index as usize];
+        if !slot.free {
+            return false;
+        }
+        slot.free = false;
+        slot.generation = id.generation;
+        slot.latest = slot.latest.max(id.generation);
+        true
+    }
+
+    pub fn set(&mut self, id: EntityId, location: (TableId, RowIndex)) {
+        if let Some(slot) = self
+            .slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+        {
+            slot.location = Some(location);
+        }
+    }
+
+    /// Frees a live entity, ids that are only reserved have no location to
+    /// free and are left alone so their pending spawn still owns the slot
+    pub fn free(&mut self, id: EntityId) -> Option<(TableId, RowIndex)> {
+        let slot = self
+            .slots
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation && slot.location.is_some())?;
+
+        slot.next_generation();
+        self.free.push(id.index);
+        slot.location.take()
+    }
+
+    /// Gives back a reserved id that was never spawned
+    pub fn release(&mut self, id: EntityId) {
+        if self.is_reserved(id) {
+            self.slots[id.index as usize].next_generation();
+            self.free.push(id.index);
+        }
+    }
+
+    /// Whether `id` has been reserved but not spawned yet
+    pub fn is_reserved(&self, id: EntityId) -> bool {
+        self.slots.get(id.index as usize).is_some_and(|slot| {
+            slot.generation == id.generation && slot.location.is_none() && !slot.free
+        })
+    }
+
+    pub fn get(&self, id: EntityId) -> Option<(TableId, RowIndex)> {
+        self.slots
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.location)
+    }
+
+    pub fn is_alive(&self, id: EntityId) -> bool {
+        self.get(id).is_some()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, (TableId, RowIndex))> + '_ {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.location.map(|location| {
+                (
+                    EntityId {
+                        index: index as u32,
+                        generation: slot.generation,
+                    },
+                    location,
+                )
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_stale() {
+        let mut entities = Entities::default();
+        let location = (TableId(0), RowIndex(0));
+        let a = entities.reserve();
+        assert!(!entities.is_alive(a));
+        entities.set(a, location);
+        assert!(entities.is_alive(a));
+        assert_eq!(entities.free(a), Some(location));
+        assert!(!entities.is_alive(a));
+
+        let b = entities.reserve();
+        entities.set(a, location);
+        entities.set(b, location);
+        assert_eq!(a.index(), b.index());
+        assert_ne!(a, b);
+        assert_eq!(entities.get(a), None);
+        assert_eq!(entities.free(a), None);
+        assert!(entities.is_alive(b));
+    }
+
+    #[test]
+    pub fn test_reserved() {
+        let mut entities = Entities::default();
+        let a = entities.reserve();
+        assert!(entities.is_reserved(a));
+        assert_eq!(entities.free(a), None);
+        assert_ne!(entities.reserve().index(), a.index());
+
+        entities.release(a);
+        assert!(!entities.is_reserved(a));
+        let b = entities.reserve();
+        assert_eq!(b.index(), a.index());
+        assert_ne!(a, b);
+    }
+}
@@ -0,0 +1,186 @@
+use std::marker::PhantomData;
+
+use crate::sync::{Cell, RefMut};
+
+/// Double buffered queue of events of one type, events are kept until the
+/// end of the tick after they were sent so every system gets a chance to
+/// read them whatever order they run in.
+pub struct Events<T> {
+    previous: Vec<T>,
+    current: Vec<T>,
+    /// Id of the first event in `previous`
+    start: usize,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            previous: Vec::new(),
+            current: Vec::new(),
+            start: 0,
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.current.push(event)
+    }
+
+    /// Drops the events sent before the last update, called at the end of
+    /// every [`World::tick`](crate::World::tick) for registered event types.
+    pub fn update(&mut self) {
+        self.start += self.previous.len();
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.previous.len() + self.current.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Id the next event sent will have
+    fn end(&self) -> usize {
+        self.start + self.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.previous.iter().chain(&self.current)
+    }
+}
+
+/// Sends events to an [`Events`] queue
+pub struct EventWriter<'a, T> {
+    pub(crate) events: RefMut<'a, Events<T>>,
+}
+
+impl<T> EventWriter<'_, T> {
+    pub fn send(&mut self, event: T) {
+        self.events.send(event)
+    }
+}
+
+/// Cursor into an [`Events`] queue, each reader sees every event once as long
+/// as it reads at least once a tick.
+pub struct EventReader<T> {
+    next: Cell<usize>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for EventReader<T> {
+    fn default() -> Self {
+        Self {
+            next: Cell::new(0),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> EventReader<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Events sent since this reader last read
+    pub fn read<'a>(&self, events: &'a Events<T>) -> impl Iterator<Item = &'a T> {
+        let skip = self.next.get().saturating_sub(events.start);
+        self.next.set(events.end());
+        events.iter().skip(skip)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sync::Shared, World};
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Damage(u32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    enum Event {
+        Damage(u32),
+        Other,
+    }
+
+    #[test]
+    pub fn test_double_buffer() {
+        let mut events = Events::default();
+        let reader = EventReader::new();
+        let late = EventReader::new();
+
+        events.send(Damage(0));
+        events.send(Damage(1));
+        assert_eq!(reader.read(&events).count(), 2);
+        assert_eq!(reader.read(&events).count(), 0);
+
+        events.update();
+        events.send(Damage(2));
+        assert_eq!(events.len(), 3);
+        assert_eq!(reader.read(&events).collect::<Vec<_>>(), vec![&Damage(2)]);
+
+        events.update();
+        events.send(Damage(3));
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            late.read(&events).collect::<Vec<_>>(),
+            vec![&Damage(2), &Damage(3)]
+        );
+        assert_eq!(reader.read(&events).collect::<Vec<_>>(), vec![&Damage(3)]);
+    }
+
+    #[test]
+    pub fn test_readers() {
+        let read = Shared::new(Cell::new(0));
+        let world = World::<()>::new()
+            .with_event::<Damage>()
+            .with_ticker({
+                let read = read.clone();
+                let reader = EventReader::<Damage>::new();
+                move |world| {
+                    let events = world.events::<Damage>().unwrap();
+                    read.set(read.get() + reader.read(&events).map(|x| x.0).sum::<u32>())
+                }
+            })
+            .with_ticker(|world| world.event_writer::<Damage>().unwrap().send(Damage(1)))
+            .with_ticker({
+                let read = read.clone();
+                let reader = EventReader::<Damage>::new();
+                move |world| {
+                    let events = world.events::<Damage>().unwrap();
+                    read.set(read.get() + 10 * reader.read(&events).map(|x| x.0).sum::<u32>())
+                }
+            });
+
+        world.send(Damage(2));
+        world.tick();
+        assert_eq!(read.get(), 32);
+        world.tick();
+        assert_eq!(read.get(), 32 + 11);
+        world.tick();
+        assert_eq!(read.get(), 32 + 11 + 11);
+        assert_eq!(world.events::<Damage>().unwrap().len(), 1);
+    }
+
+    #[test]
+    pub fn test_from_submitted() {
+        let world = World::<Event>::new().with_event_from(|event| match event {
+            Event::Damage(amount) => Some(Damage(*amount)),
+            _ => None,
+        });
+        let reader = EventReader::<Damage>::new();
+
+        world.submit(Event::Damage(1));
+        world.submit(Event::Other);
+        world.submit(Event::Damage(2));
+        let events = world.events::<Damage>().unwrap();
+        assert_eq!(
+            reader.read(&events).collect::<Vec<_>>(),
+            vec![&Damage(1), &Damage(2)]
+        );
+    }
+}
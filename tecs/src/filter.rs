@@ -0,0 +1,418 @@
+//! Filters built out of other queries, e.g.
+//! `world.query::<(&mut Transform, Or<(Changed<Transform>, Added<Mesh>)>)>()`
+
+use std::{any::TypeId, marker::PhantomData};
+
+use crate::{Access, Archetype, Fetch, Join, Query, QueryOne, RowIndex, SparseSets, Table, Ticks};
+
+/// Matches rows matched by any query in the tuple, only the rows are filtered
+/// and the data of the queries isn't handed out.
+pub struct Or<T>(PhantomData<T>);
+
+/// Every query in the tuple as an `Option`, matching entities with at least
+/// one of them, e.g. `AnyOf<(&Health, &mut Shield)>`
+pub struct AnyOf<T>(PhantomData<T>);
+
+/// Row by row results of an [`Or`] filter
+pub struct RowFilter {
+    tables: Vec<Vec<bool>>,
+}
+
+impl RowFilter {
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        self.tables.iter().flatten().copied()
+    }
+}
+
+pub struct RowFetch<'b> {
+    tables: &'b [Vec<bool>],
+}
+
+impl Fetch for RowFetch<'_> {
+    type Item = ();
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.tables.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.tables[table].len())
+    }
+
+    fn matches(&self, table: usize, row: usize) -> bool {
+        self.tables[table][row]
+    }
+
+    unsafe fn get(&mut self, _: usize, _: usize) -> Self::Item {}
+}
+
+impl Join for RowFilter {
+    type Fetch<'b> = RowFetch<'b>;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        RowFetch {
+            tables: &self.tables,
+        }
+    }
+}
+
+/// Which rows of `table` a query matches, every row for queries that only
+/// filter tables
+fn rows<E, Q: Query<E>>(table: (TypeId, &Table), sparse: &SparseSets, ticks: Ticks) -> Vec<bool>
+where
+    for<'a> Q::Output<'a>: Join,
+{
+    if !Q::filter(&table) {
+        return vec![false; table.1.len()];
+    }
+
+    let mut output = Q::data(&[table], sparse, ticks);
+    let fetch = output.fetch();
+    (0..table.1.len())
+        .map(|row| fetch.matches(0, row))
+        .collect()
+}
+
+/// Whether `row` of `table` passes `Q`
+fn row_matches<E, Q: Query<E>>(
+    table: (TypeId, &Table),
+    row: RowIndex,
+    sparse: &SparseSets,
+    ticks: Ticks,
+) -> bool
+where
+    for<'a> Q::Output<'a>: Join,
+{
+    Q::filter(&table)
+        && Q::data(&[table], sparse, ticks)
+            .fetch()
+            .matches(0, row.0 as usize)
+}
+
+macro_rules! impl_or {
+    ($($ty:ident)+) => {
+        impl<E, $($ty: Query<E>),+> Query<E> for Or<($($ty,)+)>
+        where
+            $(for<'a> $ty::Output<'a>: Join),+
+        {
+            type Output<'a> = RowFilter;
+
+            fn filter(table: &(TypeId, &Table)) -> bool {
+                $($ty::filter(table))||+
+            }
+
+            fn data<'a>(tables: &[(TypeId, &'a Table)], sparse: &'a SparseSets, ticks: Ticks) -> Self::Output<'a> {
+                RowFilter {
+                    tables: tables
+                        .iter()
+                        .map(|table| {
+                            let mut matched = vec![false; table.1.len()];
+                            $(
+                                matched
+                                    .iter_mut()
+                                    .zip(rows::<E, $ty>(*table, sparse, ticks))
+                                    .for_each(|(matched, row)| *matched |= row);
+                            )+
+                            matched
+                        })
+                        .collect(),
+                }
+            }
+
+            fn access(access: &mut Access) {
+                $($ty::access(access);)+
+            }
+        }
+
+        impl<E, $($ty: Query<E>),+> QueryOne<E> for Or<($($ty,)+)>
+        where
+            $(for<'a> $ty::Output<'a>: Join),+
+        {
+            type Output<'a> = bool;
+
+            fn filter(table: &(TypeId, &Table)) -> bool {
+                $($ty::filter(table))||+
+            }
+
+            fn matches(table: (TypeId, &Table), row: RowIndex, sparse: &SparseSets, ticks: Ticks) -> bool {
+                $(row_matches::<E, $ty>(table, row, sparse, ticks))||+
+            }
+
+            fn data<'a>(table: (TypeId, &'a Table), row: RowIndex, sparse: &'a SparseSets, ticks: Ticks) -> Self::Output<'a> {
+                <Self as QueryOne<E>>::matches(table, row, sparse, ticks)
+            }
+
+            fn access(access: &mut Access) {
+                $($ty::access(access);)+
+            }
+        }
+
+        impl<E, $($ty: Query<E>),+> Query<E> for AnyOf<($($ty,)+)>
+        where
+            $(Option<$ty>: Query<E>),+
+        {
+            type Output<'a> = ($(<Option<$ty> as Query<E>>::Output<'a>,)+);
+
+            fn filter(table: &(TypeId, &Table)) -> bool {
+                $($ty::filter(table))||+
+            }
+
+            fn data<'a>(tables: &[(TypeId, &'a Table)], sparse: &'a SparseSets, ticks: Ticks) -> Self::Output<'a> {
+                ($(<Option<$ty> as Query<E>>::data(tables, sparse, ticks),)+)
+            }
+
+            fn access(access: &mut Access) {
+                $(<Option<$ty> as Query<E>>::access(access);)+
+            }
+        }
+
+        impl<E, $($ty: QueryOne<E>),+> QueryOne<E> for AnyOf<($($ty,)+)>
+        where
+            $(Option<$ty>: QueryOne<E>),+
+        {
+            type Output<'a> = ($(<Option<$ty> as QueryOne<E>>::Output<'a>,)+);
+
+            fn filter(table: &(TypeId, &Table)) -> bool {
+                $($ty::filter(table))||+
+            }
+
+            fn data<'a>(table: (TypeId, &'a Table), row: RowIndex, sparse: &'a SparseSets, ticks: Ticks) -> Self::Output<'a> {
+                ($(<Option<$ty> as QueryOne<E>>::data(table, row, sparse, ticks),)+)
+            }
+
+            fn access(access: &mut Access) {
+                $(<Option<$ty> as QueryOne<E>>::access(access);)+
+            }
+        }
+    };
+}
+
+impl_or!(A);
+impl_or!(A B);
+impl_or!(A B C);
+impl_or!(A B C D);
+impl_or!(A B C D E1);
+impl_or!(A B C D E1 F);
+impl_or!(A B C D E1 F G);
+impl_or!(A B C D E1 F G H);
+
+/// An archetype or a tuple of archetypes, so `Is<(Player, Enemy)>` matches
+/// entities of either
+pub trait Archetypes {
+    fn contains(ty: TypeId) -> bool;
+}
+
+impl<T: Archetype> Archetypes for T {
+    fn contains(ty: TypeId) -> bool {
+        ty == TypeId::of::<T>()
+    }
+}
+
+macro_rules! impl_archetypes {
+    ($($ty:ident)+) => {
+        impl<$($ty: Archetype),+> Archetypes for ($($ty,)+) {
+            fn contains(ty: TypeId) -> bool {
+                $(ty == TypeId::of::<$ty>())||+
+            }
+        }
+    };
+}
+
+impl_archetypes!(A);
+impl_archetypes!(A B);
+impl_archetypes!(A B C);
+impl_archetypes!(A B C D);
+impl_archetypes!(A B C D E);
+impl_archetypes!(A B C D E F);
+impl_archetypes!(A B C D E F G);
+impl_archetypes!(A B C D E F G H);
+
+#[cfg(test)]
+mod tests {
+    use crate::{prelude::*, Added, Changed, World};
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        value: u32,
+        name: String,
+    }
+
+    #[derive(Archetype, Clone)]
+    struct Other {
+        value: u32,
+        scale: f32,
+    }
+
+    #[derive(Archetype, Clone)]
+    struct Unrelated {
+        scale: f32,
+    }
+
+    fn world() -> (World<()>, [EntityId; 3]) {
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .register_unsaved::<Other>()
+            .register_unsaved::<Unrelated>();
+        let thing = world.spawn(Thing {
+            value: 0,
+            name: String::from("thing"),
+        });
+        let other = world.spawn(Other {
+            value: 1,
+            scale: 2.0,
+        });
+        let unrelated = world.spawn(Unrelated { scale: 3.0 });
+        world.tick();
+        world.tick();
+        (world, [thing, other, unrelated])
+    }
+
+    fn sorted(mut entities: Vec<EntityId>) -> Vec<EntityId> {
+        entities.sort_by_key(|entity| entity.index());
+        entities
+    }
+
+    #[test]
+    pub fn test_or() {
+        let (world, [thing, other, unrelated]) = world();
+
+        let mut query = world.query::<(EntityId, Or<(With<String>, Changed<f32>)>)>();
+        let matched = query.iter_mut().map(|(id, _)| *id).collect();
+        assert_eq!(sorted(matched), vec![thing]);
+        drop(query);
+
+        *world.get_component_mut::<f32>(unrelated).unwrap() += 1.0;
+        let mut query = world.query::<(EntityId, Or<(With<String>, Changed<f32>)>)>();
+        let matched = query.iter_mut().map(|(id, _)| *id).collect();
+        assert_eq!(sorted(matched), vec![thing, unrelated]);
+        drop(query);
+
+        world.tick();
+        world.spawn(Other {
+            value: 4,
+            scale: 5.0,
+        });
+        let (values, filter) = world.query::<(&u32, Or<(Added<u32>, Changed<f32>)>)>();
+        let matched = values
+            .iter()
+            .zip(filter.iter())
+            .filter(|(_, matched)| *matched)
+            .map(|(value, _)| *value)
+            .collect::<Vec<_>>();
+        assert_eq!(matched, vec![4]);
+        drop((values, filter));
+
+        world.tick();
+        world.tick();
+        *world.get_component_mut::<f32>(other).unwrap() += 1.0;
+        let (matched, _) = world.query_one::<(Or<(Changed<f32>, Added<f32>)>, Is<Other>)>();
+        assert!(matched);
+        let (matched, _) = world.query_one::<(Or<(Changed<String>, Added<String>)>, Is<Thing>)>();
+        assert!(!matched);
+    }
+
+    #[test]
+    pub fn test_any_of() {
+        let (world, [thing, other, _]) = world();
+
+        let (ids, (values, mut scales)) = world.query::<(EntityId, AnyOf<(&String, &mut f32)>)>();
+        let rows = ids
+            .iter()
+            .zip(values.iter().zip(scales.iter_mut()))
+            .map(|(id, (name, scale))| {
+                let scale = scale.map(|mut scale| {
+                    *scale *= 2.0;
+                    *scale
+                });
+                (*id, name.cloned(), scale)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(rows.len(), 3);
+        assert!(rows.contains(&(thing, Some(String::from("thing")), None)));
+        assert!(rows.contains(&(other, None, Some(4.0))));
+        drop((ids, values, scales));
+
+        let mut query = world.query::<(&u32, AnyOf<(&String, &mut f32)>)>();
+        let mut rows = query
+            .iter_mut()
+            .map(|(value, (name, scale))| (*value, name.is_some(), scale.is_some()))
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|(value, _, _)| *value);
+        assert_eq!(rows, vec![(0, true, false), (1, false, true)]);
+        drop(query);
+
+        let ((name, scale), _) = world.query_one::<(AnyOf<(&String, &mut f32)>, Is<Other>)>();
+        assert!(name.is_none());
+        *scale.unwrap() += 1.0;
+        assert_eq!(world.get_component::<f32>(other).as_deref(), Some(&5.0));
+    }
+
+    #[test]
+    pub fn test_optional() {
+        let (world, [thing, other, unrelated]) = world();
+
+        let (ids, values) = world.query::<(EntityId, Option<&u32>)>();
+        let mut rows = ids
+            .iter()
+            .zip(values.iter())
+            .map(|(id, value)| (*id, value.copied()))
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|(id, _)| id.index());
+        assert_eq!(
+            rows,
+            vec![(thing, Some(0)), (other, Some(1)), (unrelated, None)]
+        );
+        drop((ids, values));
+
+        let mut query = world.query::<(&f32, Option<&mut u32>)>();
+        query.iter_mut().for_each(|(_, value)| {
+            if let Some(mut value) = value {
+                *value += 10
+            }
+        });
+        drop(query);
+        assert_eq!(world.get_component::<u32>(other).as_deref(), Some(&11));
+
+        let mut values = world.query::<Option<&mut u32>>();
+        values.for_each(|value| {
+            if let Some(mut value) = value {
+                *value += 1
+            }
+        });
+        drop(values);
+        assert_eq!(world.get_component::<u32>(thing).as_deref(), Some(&1));
+
+        let mut query = world.query::<(EntityId, Changed<f32>)>();
+        assert_eq!(query.iter_mut().count(), 0);
+        drop(query);
+        let mut query = world.query::<(EntityId, Changed<u32>)>();
+        assert_eq!(
+            sorted(query.iter_mut().map(|(id, _)| *id).collect()),
+            vec![thing, other]
+        );
+        drop(query);
+
+        let (value, _) = world.query_one::<(Option<&u32>, Is<Unrelated>)>();
+        assert!(value.is_none());
+        drop(value);
+        let (value, _) = world.query_one::<(Option<&mut u32>, Is<Thing>)>();
+        *value.unwrap() = 7;
+        assert_eq!(world.get_component::<u32>(thing).as_deref(), Some(&7));
+    }
+
+    #[test]
+    pub fn test_is_tuple() {
+        let (world, [thing, other, _]) = world();
+
+        let (ids, _) = world.query::<(EntityId, Is<(Thing, Other)>)>();
+        assert_eq!(sorted(ids.iter().copied().collect()), vec![thing, other]);
+        drop(ids);
+
+        let (ids, _, _) = world.query::<(EntityId, Is<(Other, Unrelated)>, With<u32>)>();
+        assert_eq!(ids.iter().copied().collect::<Vec<_>>(), vec![other]);
+        drop(ids);
+
+        let (value, _) = world.query_one::<(&u32, Is<(Other,)>)>();
+        assert_eq!(*value, 1);
+    }
+}
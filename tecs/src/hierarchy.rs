@@ -0,0 +1,183 @@
+use crate::{Commands, EntityError, EntityId, World};
+
+/// The entity this one is attached to, maintained alongside [`Children`] by
+/// [`World::set_parent`] and [`World::remove_parent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Parent(pub EntityId);
+
+/// The entities attached to this one, in the order they were attached
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Children(pub Vec<EntityId>);
+
+impl<E> World<E> {
+    pub fn parent(&self, entity: EntityId) -> Option<EntityId> {
+        self.get_component::<Parent>(entity).map(|parent| parent.0)
+    }
+
+    pub fn children(&self, entity: EntityId) -> Vec<EntityId> {
+        self.get_component::<Children>(entity)
+            .map(|children| children.0.clone())
+            .unwrap_or_default()
+    }
+
+    /// Attaches `child` to `parent`, detaching it from its old parent first
+    pub fn set_parent(&self, child: EntityId, parent: EntityId) -> Result<(), EntityError> {
+        if !self.is_alive(child) {
+            return Err(EntityError::Dead(child));
+        }
+        if !self.is_alive(parent) {
+            return Err(EntityError::Dead(parent));
+        }
+        if std::iter::successors(Some(parent), |entity| self.parent(*entity))
+            .any(|ancestor| ancestor == child)
+        {
+            return Err(EntityError::Cycle(child));
+        }
+
+        self.detach(child);
+        self.insert_component(child, Parent(parent))?;
+        let children = self.get_component_mut::<Children>(parent);
+        match children {
+            Some(mut children) => children.0.push(child),
+            None => self.insert_component(parent, Children(vec![child]))?,
+        }
+        Ok(())
+    }
+
+    /// Detaches `child` from its parent, making it a root
+    pub fn remove_parent(&self, child: EntityId) -> Result<(), EntityError> {
+        if !self.is_alive(child) {
+            return Err(EntityError::Dead(child));
+        }
+        self.detach(child);
+        Ok(())
+    }
+
+    /// Removes `child` from its parent's [`Children`] and its [`Parent`]
+    fn detach(&self, child: EntityId) {
+        let Some(parent) = self.parent(child) else {
+            return;
+        };
+        let _ = self.remove_component::<Parent>(child);
+
+        let empty = self
+            .get_component_mut::<Children>(parent)
+            .map(|mut children| {
+                children.0.retain(|other| *other != child);
+                children.0.is_empty()
+            });
+        if empty == Some(true) {
+            let _ = self.remove_component::<Children>(parent);
+        }
+    }
+
+    /// Unlinks an entity from the hierarchy before it is despawned, its
+    /// children become roots
+    pub(crate) fn unlink(&self, entity: EntityId) {
+        self.detach(entity);
+        if let Ok(children) = self.remove_component::<Children>(entity) {
+            children.0.into_iter().for_each(|child| {
+                let _ = self.remove_component::<Parent>(child);
+            })
+        }
+    }
+
+    /// Despawns an entity along with all of its descendants
+    pub fn despawn_recursive(&self, entity: EntityId) -> Result<(), EntityError> {
+        if !self.is_alive(entity) {
+            return Err(EntityError::Dead(entity));
+        }
+        self.detach(entity);
+
+        let mut stack = vec![entity];
+        while let Some(entity) = stack.pop() {
+            if let Ok(children) = self.remove_component::<Children>(entity) {
+                stack.extend(children.0);
+            }
+            let _ = self.remove_component::<Parent>(entity);
+            self.despawn(entity)?;
+        }
+        Ok(())
+    }
+}
+
+impl<E> Commands<'_, E> {
+    pub fn set_parent(&self, child: EntityId, parent: EntityId) {
+        self.push(move |world| {
+            let _ = world.set_parent(child, parent);
+        })
+    }
+
+    pub fn remove_parent(&self, child: EntityId) {
+        self.push(move |world| {
+            let _ = world.remove_parent(child);
+        })
+    }
+
+    pub fn despawn_recursive(&self, entity: EntityId) {
+        self.push(move |world| {
+            let _ = world.despawn_recursive(entity);
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{prelude::*, EntityError, World};
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        value: u32,
+    }
+
+    fn world() -> (World<()>, Vec<EntityId>) {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let ids = (0..4).map(|value| world.spawn(Thing { value })).collect();
+        (world, ids)
+    }
+
+    #[test]
+    pub fn test_set_parent() {
+        let (world, ids) = world();
+        world.set_parent(ids[1], ids[0]).unwrap();
+        world.set_parent(ids[2], ids[0]).unwrap();
+        world.set_parent(ids[3], ids[1]).unwrap();
+        assert_eq!(world.children(ids[0]), vec![ids[1], ids[2]]);
+        assert_eq!(world.parent(ids[3]), Some(ids[1]));
+        assert_eq!(
+            world.set_parent(ids[0], ids[3]),
+            Err(EntityError::Cycle(ids[0]))
+        );
+
+        world.set_parent(ids[1], ids[2]).unwrap();
+        assert_eq!(world.children(ids[0]), vec![ids[2]]);
+        assert_eq!(world.children(ids[2]), vec![ids[1]]);
+
+        world.remove_parent(ids[2]).unwrap();
+        assert_eq!(world.parent(ids[2]), None);
+        assert!(world.get_component::<Children>(ids[0]).is_none());
+
+        world.despawn(ids[1]).unwrap();
+        assert!(world.children(ids[2]).is_empty());
+        assert_eq!(world.parent(ids[3]), None);
+    }
+
+    #[test]
+    pub fn test_despawn_recursive() {
+        let (world, ids) = world();
+        world.set_parent(ids[1], ids[0]).unwrap();
+        world.set_parent(ids[2], ids[1]).unwrap();
+        world.set_parent(ids[3], ids[0]).unwrap();
+
+        let commands = world.commands();
+        commands.despawn_recursive(ids[1]);
+        world.apply_commands();
+        assert!(world.is_alive(ids[0]));
+        assert!(!world.is_alive(ids[1]));
+        assert!(!world.is_alive(ids[2]));
+        assert_eq!(world.children(ids[0]), vec![ids[3]]);
+
+        world.despawn_recursive(ids[0]).unwrap();
+        assert_eq!(world.query::<&u32>().iter().count(), 0);
+    }
+}
@@ -0,0 +1,371 @@
+use std::{marker::PhantomData, ptr::NonNull};
+
+use crate::{Columns, ColumnsMut, ColumnsOptional, ColumnsOptionalMut, ComponentTicks, Mut};
+
+/// Random access into the tables of a query result, used to walk several
+/// results row by row in lockstep.
+pub trait Fetch {
+    type Item;
+
+    /// Number of tables, or `None` for filters that don't hold any data
+    fn tables(&self) -> Option<usize> {
+        None
+    }
+
+    fn rows(&self, _table: usize) -> Option<usize> {
+        None
+    }
+
+    /// Whether the row should be yielded at all, for filters like `Changed`
+    fn matches(&self, _table: usize, _row: usize) -> bool {
+        true
+    }
+
+    /// # Safety
+    /// The row must be in bounds and each row may only be fetched once
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item;
+}
+
+/// Query results that can be iterated row by row, yielding one item per
+/// entity, e.g. `for (transform, positions) in query.iter_mut()`
+pub trait Join {
+    type Fetch<'b>: Fetch
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_>;
+
+    fn iter_mut(&mut self) -> JoinIter<Self::Fetch<'_>> {
+        JoinIter {
+            fetch: self.fetch(),
+            table: 0,
+            row: 0,
+        }
+    }
+}
+
+pub struct JoinIter<F> {
+    fetch: F,
+    table: usize,
+    row: usize,
+}
+
+impl<F: Fetch> Iterator for JoinIter<F> {
+    type Item = F::Item;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.table >= self.fetch.tables()? {
+                return None;
+            }
+
+            if self.row >= self.fetch.rows(self.table)? {
+                self.table += 1;
+                self.row = 0;
+                continue;
+            }
+
+            self.row += 1;
+            if !self.fetch.matches(self.table, self.row - 1) {
+                continue;
+            }
+            // SAFETY: rows are bounds checked above and only visited once
+            return Some(unsafe { self.fetch.get(self.table, self.row - 1) });
+        }
+    }
+}
+
+pub struct SliceFetch<'b, T> {
+    slices: Vec<&'b [T]>,
+}
+
+impl<'b, T> Fetch for SliceFetch<'b, T> {
+    type Item = &'b T;
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.slices.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.slices[table].len())
+    }
+
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+        &self.slices[table][row]
+    }
+}
+
+impl<T> Join for Columns<'_, T> {
+    type Fetch<'b>
+        = SliceFetch<'b, T>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        SliceFetch {
+            slices: self.columns.iter().map(|column| &**column).collect(),
+        }
+    }
+}
+
+pub struct SliceFetchMut<'b, T> {
+    slices: Vec<(NonNull<T>, usize)>,
+    ticks: Vec<&'b [ComponentTicks]>,
+    tick: u32,
+    _marker: PhantomData<&'b mut T>,
+}
+
+impl<'b, T> Fetch for SliceFetchMut<'b, T> {
+    type Item = Mut<'b, T>;
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.slices.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.slices[table].1)
+    }
+
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+        let ticks = self.ticks[table];
+        Mut {
+            value: unsafe { &mut *self.slices[table].0.as_ptr().add(row) },
+            ticks: &ticks[row],
+            tick: self.tick,
+        }
+    }
+}
+
+impl<T> Join for ColumnsMut<'_, T> {
+    type Fetch<'b>
+        = SliceFetchMut<'b, T>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        SliceFetchMut {
+            slices: self
+                .columns
+                .iter_mut()
+                .map(|column| (NonNull::from(&mut **column).cast(), column.len()))
+                .collect(),
+            ticks: self.ticks.iter().map(|ticks| &**ticks).collect(),
+            tick: self.tick,
+            _marker: PhantomData,
+        }
+    }
+}
+
+pub struct OptionalFetch<'b, T> {
+    slices: Vec<Result<&'b [T], usize>>,
+}
+
+impl<'b, T> Fetch for OptionalFetch<'b, T> {
+    type Item = Option<&'b T>;
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.slices.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(match self.slices[table] {
+            Ok(slice) => slice.len(),
+            Err(len) => len,
+        })
+    }
+
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+        self.slices[table].ok().map(|slice| &slice[row])
+    }
+}
+
+impl<T> Join for ColumnsOptional<'_, T> {
+    type Fetch<'b>
+        = OptionalFetch<'b, T>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        OptionalFetch {
+            slices: self
+                .columns
+                .iter()
+                .map(|column| column.as_deref().map_err(|len| *len))
+                .collect(),
+        }
+    }
+}
+
+pub struct OptionalFetchMut<'b, T> {
+    slices: Vec<(Option<NonNull<T>>, usize)>,
+    ticks: Vec<&'b [ComponentTicks]>,
+    tick: u32,
+    _marker: PhantomData<&'b mut T>,
+}
+
+impl<'b, T> Fetch for OptionalFetchMut<'b, T> {
+    type Item = Option<Mut<'b, T>>;
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.slices.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.slices[table].1)
+    }
+
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+        let column = self.slices[table].0?;
+        let ticks = self.ticks[table];
+        Some(Mut {
+            value: unsafe { &mut *column.as_ptr().add(row) },
+            ticks: &ticks[row],
+            tick: self.tick,
+        })
+    }
+}
+
+impl<T> Join for ColumnsOptionalMut<'_, T> {
+    type Fetch<'b>
+        = OptionalFetchMut<'b, T>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        let (slices, ticks) = self
+            .columns
+            .iter_mut()
+            .map(|column| match column {
+                Ok((column, ticks)) => (
+                    (Some(NonNull::from(&mut **column).cast()), column.len()),
+                    &**ticks,
+                ),
+                Err(len) => ((None, *len), &[][..]),
+            })
+            .unzip();
+        OptionalFetchMut {
+            slices,
+            ticks,
+            tick: self.tick,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Fetch for () {
+    type Item = ();
+
+    unsafe fn get(&mut self, _: usize, _: usize) -> Self::Item {}
+}
+
+impl Join for () {
+    type Fetch<'b> = ();
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {}
+}
+
+macro_rules! impl_join {
+    ($($ty:ident $index:tt)+) => {
+        impl<$($ty: Fetch),+> Fetch for ($($ty),+,) {
+            type Item = ($($ty::Item),+,);
+
+            fn tables(&self) -> Option<usize> {
+                None$(.or(self.$index.tables()))+
+            }
+
+            fn rows(&self, table: usize) -> Option<usize> {
+                None$(.or(self.$index.rows(table)))+
+            }
+
+            fn matches(&self, table: usize, row: usize) -> bool {
+                $(self.$index.matches(table, row))&&+
+            }
+
+            unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+                unsafe { ($(self.$index.get(table, row)),+,) }
+            }
+        }
+
+        impl<$($ty: Join),+> Join for ($($ty),+,) {
+            type Fetch<'b> = ($($ty::Fetch<'b>),+,) where Self: 'b;
+
+            fn fetch(&mut self) -> Self::Fetch<'_> {
+                ($(self.$index.fetch()),+,)
+            }
+        }
+    };
+}
+
+impl_join!(A 0);
+impl_join!(A 0 B 1);
+impl_join!(A 0 B 1 C 2);
+impl_join!(A 0 B 1 C 2 D 3);
+impl_join!(A 0 B 1 C 2 D 3 E 4);
+impl_join!(A 0 B 1 C 2 D 3 E 4 F 5);
+impl_join!(A 0 B 1 C 2 D 3 E 4 F 5 G 6);
+impl_join!(A 0 B 1 C 2 D 3 E 4 F 5 G 6 H 7);
+
+#[cfg(test)]
+mod tests {
+    use crate::{prelude::*, World};
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        value: u32,
+        name: String,
+    }
+
+    #[derive(Archetype, Clone)]
+    struct Other {
+        value: u32,
+        scale: f32,
+    }
+
+    #[test]
+    pub fn test_join() {
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .register_unsaved::<Other>();
+        let a = world.spawn(Thing {
+            value: 0,
+            name: String::from("a"),
+        });
+        let b = world.spawn(Other {
+            value: 1,
+            scale: 2.0,
+        });
+        let c = world.spawn(Thing {
+            value: 2,
+            name: String::from("c"),
+        });
+
+        let mut query = world.query::<(EntityId, &mut u32, Option<&Thing>, &String)>();
+        let mut rows = query
+            .iter_mut()
+            .map(|(id, mut value, _, name)| {
+                *value += 10;
+                (*id, *value, name.clone())
+            })
+            .collect::<Vec<_>>();
+        rows.sort_by_key(|(_, value, _)| *value);
+        assert_eq!(
+            rows,
+            vec![(a, 10, String::from("a")), (c, 12, String::from("c"))]
+        );
+        drop(query);
+
+        let mut query = world.query::<(&u32, &mut f32, Is<Other>)>();
+        query
+            .iter_mut()
+            .for_each(|(value, mut scale, _)| *scale *= *value as f32 + 1.0);
+        drop(query);
+        assert_eq!(*world.get_component::<f32>(b).unwrap(), 4.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "aliased mutable access to u32")]
+    pub fn test_aliased() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        world.query::<(&mut u32, &u32)>();
+    }
+}
@@ -1,27 +1,60 @@
-#![feature(impl_trait_in_assoc_type)]
-#![feature(vec_into_raw_parts)]
-
+extern crate self as tecs;
+
+mod access;
+mod change;
+mod commands;
+mod entity;
+mod events;
+mod filter;
+mod hierarchy;
+mod join;
 pub mod prelude;
+pub mod reflect;
 pub mod scene;
+mod schedule;
+mod snapshot;
+mod sparse;
+mod storage;
+pub mod sync;
+pub mod system;
 pub mod utils;
 mod vecany;
 
-use serde::{
-    de::DeserializeSeed,
-    Deserialize, Serialize,
-};
+use serde::{de::DeserializeSeed, Deserialize, Serialize};
 use vecany::VecAny;
 
+pub use access::Access;
+pub use change::{Added, Changed, ComponentMut, ComponentTicks, Mut, TickFilter, Ticks};
+use commands::Command;
+pub use commands::Commands;
+use entity::Entities;
+pub use entity::{EntityError, EntityId};
+pub use events::{EventReader, EventWriter, Events};
+pub use filter::{AnyOf, Archetypes, Or, RowFilter};
+pub use hierarchy::{Children, Parent};
+pub use join::{Fetch, Join, JoinIter};
+use reflect::Component;
+use scene::{Migration, Prefab};
+use schedule::Scheduled;
+pub use schedule::{resource_changed, resource_equals, resource_exists, ScheduleError, Stage};
+pub use snapshot::{Snapshot, SnapshotBuffer, SnapshotFilter};
+pub use sparse::{Sparse, SparseColumn, SparseColumnMut, SparseSet, SparseSets};
+pub use storage::TableId;
+use storage::Tables;
+pub use sync::MaybeSync;
+use sync::{
+    filter_map_mut, filter_map_ref, Cell, DynResource, Mutex, Ref, RefCell, RefMut, Shared,
+};
+use system::SystemFn;
+
 use std::{
-    any::{Any, TypeId},
-    cell::{Cell, Ref, RefCell, RefMut},
+    any::{type_name, Any, TypeId},
     collections::HashMap,
     marker::PhantomData,
-    ops::{Deref, DerefMut},
-    rc::Rc,
+    ops::Deref,
 };
 
-pub trait System<E> {
+pub trait System<E>: MaybeSync {
     fn event(&self, _world: &World<E>, _event: &E) {}
     fn tick(&self, _world: &World<E>) {}
 }
@@ -33,28 +66,39 @@ pub trait SystemMut<E> {
 struct Handler<T>(T);
 struct Ticker<T>(T);
 
-impl<E, T: Fn(&World<E>, &E)> System<E> for Handler<T> {
+impl<E, T: Fn(&World<E>, &E) + MaybeSync> System<E> for Handler<T> {
     fn event(&self, world: &World<E>, event: &E) {
         self.0(world, event)
     }
 }
 
-impl<E, T: Fn(&World<E>)> System<E> for Ticker<T> {
+impl<E, T: Fn(&World<E>) + MaybeSync> System<E> for Ticker<T> {
     fn tick(&self, world: &World<E>) {
         self.0(world)
     }
 }
 
-impl<E, T: SystemMut<E>> System<E> for RefCell<T> {
+impl<E, T: SystemMut<E> + MaybeSync> System<E> for RefCell<T> {
     fn tick(&self, world: &World<E>) {
         self.borrow_mut().tick(world)
     }
 }
 
 pub trait Archetype: Any {
-    fn columns() -> Vec<TypeId>;
-    fn add(self, table: &Table) -> RowIndex;
-    fn remove(table: &Table, row: RowIndex);
+    /// Identifies the archetype in saved scenes, so it must stay the same
+    /// across builds and be unique within a world
+    fn name() -> &'static str;
+    /// Schema version saved in scenes, bumped along with a migration
+    /// registered by [`World::with_migration`] whenever the fields change
+    fn version() -> u32 {
+        0
+    }
+    fn columns() -> Vec<Column>;
+    /// Names and serializers of the columns, for [`reflect`]
+    fn components() -> Vec<Component> {
+        Vec::new()
+    }
+    fn add(self, table: &Table, id: EntityId, tick: u32) -> RowIndex;
     fn get(table: &Table, row: RowIndex) -> Self
     where
         Self: Clone;
@@ -67,24 +111,45 @@ pub trait Archetype: Any {
     }
 }
 
+/// A group of components flattened into the columns of the archetypes that
+/// contain it with `#[archetype(flatten)]`, so it can be declared once and
+/// queried by its components
+pub trait Bundle: Sized {
+    fn columns() -> Vec<Column>;
+    fn components() -> Vec<Component> {
+        Vec::new()
+    }
+    fn push(self, table: &Table, tick: u32);
+    fn get(table: &Table, row: RowIndex) -> Self;
+}
+
 #[derive(Clone, Copy)]
 pub(crate) struct DeserializeArchetype<'a> {
+    ty: TableId,
     table: &'a Table,
-    func: fn(
-        &Table,
-        &mut dyn erased_serde::Deserializer<'_>,
-    ) -> Result<RowIndex, erased_serde::Error>,
+    entities: &'a Mutex<Entities>,
+    tick: u32,
+    func: DeserializeFn,
 }
 
 impl<'de> DeserializeSeed<'de> for DeserializeArchetype<'de> {
-    type Value = RowIndex;
+    type Value = EntityId;
 
     fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
         let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
-        Ok((self.func)(self.table, &mut deserializer).unwrap())
+        let id = self.entities.lock().reserve();
+        let row = match (self.func)(self.table, id, self.tick, &mut deserializer) {
+            Ok(row) => row,
+            Err(e) => {
+                self.entities.lock().release(id);
+                return Err(serde::de::Error::custom(e));
+            }
+        };
+        self.entities.lock().set(id, (self.ty, row));
+        Ok(id)
     }
 }
 
@@ -95,8 +160,8 @@ pub struct Column {
 }
 
 impl Column {
-    pub fn new(ty: TypeId) -> Self {
-        let data = VecAny::new_uninit(ty);
+    pub fn new<T: MaybeSync + 'static>() -> Self {
+        let data = VecAny::new::<T>();
         Self { data }
     }
 
@@ -109,46 +174,150 @@ impl Column {
     }
 }
 
+pub type TickedColumn<'a, T> = (RefMut<'a, [T]>, Ref<'a, [ComponentTicks]>);
+
+pub(crate) type SerializeFn = fn(&Table, RowIndex) -> Box<dyn erased_serde::Serialize>;
+pub(crate) type DeserializeFn = fn(
+    &Table,
+    EntityId,
+    u32,
+    &mut dyn erased_serde::Deserializer<'_>,
+) -> Result<RowIndex, erased_serde::Error>;
+
 pub struct Table {
     pub length: Cell<usize>,
+    pub entities: RefCell<Vec<EntityId>>,
+    pub(crate) archetype: TypeId,
+    pub(crate) name: &'static str,
+    pub(crate) version: u32,
+    /// Only filled in for the table an archetype is registered with
+    pub(crate) components: Vec<Component>,
     columns: Vec<(TypeId, RefCell<Column>)>,
-    pub(crate) serialize: Option<fn(&Self, RowIndex) -> Box<dyn erased_serde::Serialize>>,
-    pub(crate) deserialize: Option<
-        fn(&Self, &mut dyn erased_serde::Deserializer<'_>) -> Result<RowIndex, erased_serde::Error>,
-    >,
+    ticks: Vec<RefCell<Vec<ComponentTicks>>>,
+    pub(crate) serialize: Option<SerializeFn>,
+    pub(crate) deserialize: Option<DeserializeFn>,
 }
 
 impl Table {
-    pub fn new_unsaved<T: Archetype>() -> Self {
+    pub fn from_columns(archetype: TypeId, columns: Vec<Column>) -> Self {
         Self {
             length: Cell::new(0),
-            columns: T::columns()
-                .iter()
-                .cloned()
-                .map(|ty| (ty, RefCell::new(Column::new(ty))))
+            entities: RefCell::new(Vec::new()),
+            archetype,
+            name: "",
+            version: 0,
+            components: Vec::new(),
+            ticks: columns.iter().map(|_| RefCell::default()).collect(),
+            columns: columns
+                .into_iter()
+                .map(|column| (column.data.ty(), RefCell::new(column)))
                 .collect(),
             serialize: None,
             deserialize: None,
         }
     }
 
+    pub fn new_unsaved<T: Archetype>() -> Self {
+        Self {
+            name: T::name(),
+            version: T::version(),
+            components: T::components(),
+            ..Self::from_columns(TypeId::of::<T>(), T::columns())
+        }
+    }
+
     pub fn new<T: Archetype + Serialize + for<'a> Deserialize<'a> + Clone>() -> Self {
         Self {
-            length: Cell::new(0),
-            columns: T::columns()
-                .iter()
-                .cloned()
-                .map(|ty| (ty, RefCell::new(Column::new(ty))))
-                .collect(),
             serialize: Some(<T as Archetype>::serialize),
             deserialize: Some(
-                |table: &Table, deserializer: &mut dyn erased_serde::Deserializer<'_>| {
-                    <T as Deserialize>::deserialize(deserializer).map(|entity| entity.add(table))
+                |table: &Table,
+                 id: EntityId,
+                 tick: u32,
+                 deserializer: &mut dyn erased_serde::Deserializer<'_>| {
+                    <T as Deserialize>::deserialize(deserializer)
+                        .map(|entity| entity.add(table, id, tick))
                 },
             ),
+            ..Self::new_unsaved::<T>()
         }
     }
 
+    pub fn column_types(&self) -> impl Iterator<Item = TypeId> + '_ {
+        self.columns.iter().map(|(ty, _)| *ty)
+    }
+
+    fn index<T: 'static>(&self) -> Option<usize> {
+        self.columns
+            .iter()
+            .position(|(ty, _)| *ty == TypeId::of::<T>())
+    }
+
+    /// Pushes onto a single column as added at `tick`, the caller is
+    /// responsible for keeping the other columns the same length
+    pub fn push<T: MaybeSync + 'static>(&self, value: T, tick: u32) {
+        let index = self
+            .index::<T>()
+            .unwrap_or_else(|| panic!("Missing column {}", std::any::type_name::<T>()));
+        self.columns[index].1.borrow_mut().data.push(value);
+        self.ticks[index]
+            .borrow_mut()
+            .push(ComponentTicks::new(tick));
+    }
+
+    /// Swap removes a row, returning the entity that was moved into its place
+    pub(crate) fn remove_row(&self, row: RowIndex) -> Option<EntityId> {
+        self.length.set(self.length.get() - 1);
+        self.columns_mut()
+            .for_each(|mut column| column.data.swap_remove(row.0 as usize));
+        self.ticks.iter().for_each(|ticks| {
+            ticks.borrow_mut().swap_remove(row.0 as usize);
+        });
+        self.entities.borrow_mut().swap_remove(row.0 as usize);
+        self.entity(row)
+    }
+
+    /// Swap removes a row, moving every component that `into` also stores onto
+    /// the end of it. The remaining components are returned in their own
+    /// columns alongside the new row and the entity moved into the old row's place.
+    pub(crate) fn move_row(
+        &self,
+        row: RowIndex,
+        into: &Table,
+    ) -> (RowIndex, Option<EntityId>, Vec<Column>) {
+        let mut rest = Vec::new();
+        self.columns
+            .iter()
+            .zip(&self.ticks)
+            .for_each(|((ty, column), ticks)| {
+                let mut column = column.borrow_mut();
+                let ticks = ticks.borrow_mut().swap_remove(row.0 as usize);
+                match into.columns.iter().position(|(other, _)| other == ty) {
+                    Some(index) => {
+                        column.data.swap_remove_into(
+                            row.0 as usize,
+                            &mut into.columns[index].1.borrow_mut().data,
+                        );
+                        into.ticks[index].borrow_mut().push(ticks);
+                    }
+                    None => {
+                        let mut other = Column {
+                            data: column.data.new_like().unwrap(),
+                        };
+                        column
+                            .data
+                            .swap_remove_into(row.0 as usize, &mut other.data);
+                        rest.push(other);
+                    }
+                }
+            });
+        self.length.set(self.length.get() - 1);
+        into.length.set(into.length.get() + 1);
+
+        let id = self.entities.borrow_mut().swap_remove(row.0 as usize);
+        into.entities.borrow_mut().push(id);
+        (RowIndex(into.len() as u32 - 1), self.entity(row), rest)
+    }
+
     pub fn columns_mut(&self) -> impl Iterator<Item = RefMut<'_, Column>> {
         self.columns.iter().map(|(_, column)| column.borrow_mut())
     }
@@ -179,13 +348,41 @@ impl Table {
             .iter()
             .find(|(ty, _)| *ty == TypeId::of::<T>())
             .and_then(|(_, column)| {
-                RefMut::filter_map(column.borrow_mut(), |column| {
+                filter_map_mut(column.borrow_mut(), |column| {
                     column.data.downcast_mut::<T>()
                 })
-                .ok()
             })
     }
 
+    /// Borrows a column along with its change ticks
+    pub fn column_mut_ticked<T: 'static>(&self) -> Option<TickedColumn<'_, T>> {
+        Some((self.column_mut::<T>()?, self.ticks::<T>()?))
+    }
+
+    /// Borrows a single component, marking it changed at `tick` when it is
+    /// mutably dereferenced
+    pub fn component_mut<T: 'static>(
+        &self,
+        row: RowIndex,
+        tick: u32,
+    ) -> Option<ComponentMut<'_, T>> {
+        let (column, ticks) = self.column_mut_ticked::<T>()?;
+        Some(ComponentMut {
+            value: filter_map_mut(column, |column| column.get_mut(row.0 as usize))?,
+            ticks: filter_map_ref(ticks, |ticks| ticks.get(row.0 as usize))?,
+            tick,
+        })
+    }
+
+    pub fn ticks<T: 'static>(&self) -> Option<Ref<'_, [ComponentTicks]>> {
+        self.index::<T>()
+            .map(|index| Ref::map(self.ticks[index].borrow(), |ticks| ticks.as_slice()))
+    }
+
+    pub fn entity(&self, row: RowIndex) -> Option<EntityId> {
+        self.entities.borrow().get(row.0 as usize).copied()
+    }
+
     pub fn len(&self) -> usize {
         self.length.get()
     }
@@ -213,71 +410,75 @@ impl<'a, T> Columns<'a, T> {
     }
 }
 
+/// Mutable borrows of a column in each table, rows are handed out as [`Mut`]
+/// so they are only marked changed when written to.
 pub struct ColumnsMut<'a, T> {
     columns: Vec<RefMut<'a, [T]>>,
+    ticks: Vec<Ref<'a, [ComponentTicks]>>,
+    tick: u32,
 }
 
-impl<'a, T> FromIterator<RefMut<'a, [T]>> for ColumnsMut<'a, T> {
-    fn from_iter<I: IntoIterator<Item = RefMut<'a, [T]>>>(iter: I) -> Self {
+impl<'a, T> ColumnsMut<'a, T> {
+    pub(crate) fn new(columns: Vec<TickedColumn<'a, T>>, tick: u32) -> Self {
+        let (columns, ticks) = columns.into_iter().unzip();
         Self {
-            columns: iter.into_iter().collect(),
+            columns,
+            ticks,
+            tick,
         }
     }
-}
 
-impl<'a, T> ColumnsMut<'a, T> {
     pub fn iter(&self) -> impl Iterator<Item = &T> {
         self.columns.iter().flat_map(|column| column.deref())
     }
 
-    pub fn for_each<F: FnMut(&mut T)>(&mut self, f: F) {
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = Mut<'_, T>> + use<'_, 'a, T> {
+        let tick = self.tick;
         self.columns
             .iter_mut()
-            .flat_map(|column| column.deref_mut())
-            .for_each(f)
+            .zip(&self.ticks)
+            .flat_map(move |(column, ticks)| {
+                column
+                    .iter_mut()
+                    .zip(ticks.iter())
+                    .map(move |(value, ticks)| Mut { value, ticks, tick })
+            })
     }
 
-    pub fn fold<A, F: FnMut(A, &mut T) -> A>(&mut self, init: A, f: F) -> A {
-        self.columns
-            .iter_mut()
-            .flat_map(|column| column.deref_mut())
-            .fold(init, f)
+    pub fn for_each<F: FnMut(Mut<'_, T>)>(&mut self, f: F) {
+        self.iter_mut().for_each(f)
     }
 
-    pub fn map<O, F: FnMut(&mut T) -> O>(&mut self, f: F) -> Vec<O> {
-        self.columns
-            .iter_mut()
-            .flat_map(|column| column.deref_mut())
-            .map(f)
-            .collect()
+    pub fn fold<A, F: FnMut(A, Mut<'_, T>) -> A>(&mut self, init: A, f: F) -> A {
+        self.iter_mut().fold(init, f)
     }
 
-    pub fn filter_map<O, F: FnMut(&mut T) -> Option<O>>(&mut self, f: F) -> Vec<O> {
-        self.columns
-            .iter_mut()
-            .flat_map(|column| column.deref_mut())
-            .filter_map(f)
-            .collect()
+    pub fn map<O, F: FnMut(Mut<'_, T>) -> O>(&mut self, f: F) -> Vec<O> {
+        self.iter_mut().map(f).collect()
     }
 
-    pub fn first(&mut self) -> Option<&mut T> {
-        self.columns
-            .first_mut()
-            .and_then(|column| column.first_mut())
+    pub fn filter_map<O, F: FnMut(Mut<'_, T>) -> Option<O>>(&mut self, f: F) -> Vec<O> {
+        self.iter_mut().filter_map(f).collect()
     }
 
-    pub fn get_mut(&mut self, mut index: usize) -> Option<&mut T> {
-        self.columns
-            .iter_mut()
-            .flat_map(|column| column.deref_mut())
-            .find(|_| {
-                if index == 0 {
-                    true
-                } else {
-                    index -= 1;
-                    false
-                }
-            })
+    pub fn first(&mut self) -> Option<Mut<'_, T>> {
+        self.get_mut(0)
+    }
+
+    pub fn get_mut(&mut self, mut index: usize) -> Option<Mut<'_, T>> {
+        let table = self.columns.iter().position(|column| {
+            if index < column.len() {
+                true
+            } else {
+                index -= column.len();
+                false
+            }
+        })?;
+        Some(Mut {
+            value: &mut self.columns[table][index],
+            ticks: &self.ticks[table][index],
+            tick: self.tick,
+        })
     }
 }
 
@@ -285,7 +486,24 @@ pub trait QueryOne<E> {
     type Output<'a>;
 
     fn filter(table: &(TypeId, &Table)) -> bool;
-    fn data<'a>(tables: &[(TypeId, &'a Table)]) -> Self::Output<'a>;
+    /// Whether `row` passes the query's row filters, like `Changed`, only
+    /// [`World::try_query_one`] and [`Single`] skip rows that don't
+    fn matches(
+        _table: (TypeId, &Table),
+        _row: RowIndex,
+        _sparse: &SparseSets,
+        _ticks: Ticks,
+    ) -> bool {
+        true
+    }
+    /// The components of the entity at `row` of `table`
+    fn data<'a>(
+        table: (TypeId, &'a Table),
+        row: RowIndex,
+        sparse: &'a SparseSets,
+        ticks: Ticks,
+    ) -> Self::Output<'a>;
+    fn access(_access: &mut Access) {}
 }
 
 impl<T: 'static, E> QueryOne<E> for &'_ T {
@@ -295,30 +513,40 @@ impl<T: 'static, E> QueryOne<E> for &'_ T {
         table.1.has_column::<T>()
     }
 
-    fn data<'a>(tables: &[(TypeId, &'a Table)]) -> Self::Output<'a> {
-        tables
-            .iter()
-            .find_map(|(_, table)| {
-                Ref::filter_map(table.column::<T>()?, |column| column.first()).ok()
-            })
-            .unwrap()
+    fn access(access: &mut Access) {
+        access.read::<T>()
+    }
+
+    fn data<'a>(
+        (_, table): (TypeId, &'a Table),
+        row: RowIndex,
+        _: &'a SparseSets,
+        _ticks: Ticks,
+    ) -> Self::Output<'a> {
+        Ref::map(table.column::<T>().unwrap(), |column| {
+            &column[row.0 as usize]
+        })
     }
 }
 
 impl<T: 'static, E> QueryOne<E> for &'_ mut T {
-    type Output<'a> = RefMut<'a, T>;
+    type Output<'a> = ComponentMut<'a, T>;
 
     fn filter(table: &(TypeId, &Table)) -> bool {
         table.1.has_column::<T>()
     }
 
-    fn data<'a>(tables: &[(TypeId, &'a Table)]) -> Self::Output<'a> {
-        tables
-            .iter()
-            .find_map(|(_, table)| {
-                RefMut::filter_map(table.column_mut::<T>()?, |column| column.first_mut()).ok()
-            })
-            .unwrap()
+    fn access(access: &mut Access) {
+        access.write::<T>()
+    }
+
+    fn data<'a>(
+        (_, table): (TypeId, &'a Table),
+        row: RowIndex,
+        _: &'a SparseSets,
+        ticks: Ticks,
+    ) -> Self::Output<'a> {
+        table.component_mut(row, ticks.this_run).unwrap()
     }
 }
 
@@ -331,8 +559,16 @@ macro_rules! impl_query_one {
                 $($ty::filter(table))&&+
             }
 
-            fn data<'a>(tables: &[(TypeId, &'a Table)]) -> Self::Output<'a> {
-                ($($ty::data(tables)),+,)
+            fn matches(table: (TypeId, &Table), row: RowIndex, sparse: &SparseSets, ticks: Ticks) -> bool {
+                $($ty::matches(table, row, sparse, ticks))&&+
+            }
+
+            fn data<'a>(table: (TypeId, &'a Table), row: RowIndex, sparse: &'a SparseSets, ticks: Ticks) -> Self::Output<'a> {
+                ($($ty::data(table, row, sparse, ticks)),+,)
+            }
+
+            fn access(access: &mut Access) {
+                $($ty::access(access);)+
             }
         }
     };
@@ -352,9 +588,11 @@ pub trait Query<E> {
 
     fn filter(table: &(TypeId, &Table)) -> bool;
     fn data<'a>(
-        entities: &HashMap<EntityId, (TypeId, RowIndex)>,
         tables: &[(TypeId, &'a Table)],
+        sparse: &'a SparseSets,
+        ticks: Ticks,
     ) -> Self::Output<'a>;
+    fn access(_access: &mut Access) {}
 }
 
 impl<T: 'static, E> Query<E> for &'_ T {
@@ -364,9 +602,14 @@ impl<T: 'static, E> Query<E> for &'_ T {
         table.1.has_column::<T>()
     }
 
+    fn access(access: &mut Access) {
+        access.read::<T>()
+    }
+
     fn data<'a>(
-        _: &HashMap<EntityId, (TypeId, RowIndex)>,
         tables: &[(TypeId, &'a Table)],
+        _: &'a SparseSets,
+        _ticks: Ticks,
     ) -> Self::Output<'a> {
         tables
             .iter()
@@ -382,14 +625,22 @@ impl<T: 'static, E> Query<E> for &'_ mut T {
         table.1.has_column::<T>()
     }
 
+    fn access(access: &mut Access) {
+        access.write::<T>()
+    }
+
     fn data<'a>(
-        _: &HashMap<EntityId, (TypeId, RowIndex)>,
         tables: &[(TypeId, &'a Table)],
+        _: &'a SparseSets,
+        ticks: Ticks,
     ) -> Self::Output<'a> {
-        tables
-            .iter()
-            .map(|(_, table)| table.column_mut().unwrap())
-            .collect()
+        ColumnsMut::new(
+            tables
+                .iter()
+                .map(|(_, table)| table.column_mut_ticked().unwrap())
+                .collect(),
+            ticks.this_run,
+        )
     }
 }
 
@@ -402,8 +653,12 @@ macro_rules! impl_query {
                 $($ty::filter(table))&&+
             }
 
-            fn data<'a>(entities: &HashMap<EntityId, (TypeId, RowIndex)>, tables: &[(TypeId, &'a Table)]) -> Self::Output<'a> {
-                ($($ty::data(entities, tables)),+,)
+            fn data<'a>(tables: &[(TypeId, &'a Table)], sparse: &'a SparseSets, ticks: Ticks) -> Self::Output<'a> {
+                ($($ty::data(tables, sparse, ticks)),+,)
+            }
+
+            fn access(access: &mut Access) {
+                $($ty::access(access);)+
             }
         }
     };
@@ -426,11 +681,7 @@ impl<E, T: 'static> Query<E> for With<T> {
         table.1.has_column::<T>()
     }
 
-    fn data<'a>(
-        _: &HashMap<EntityId, (TypeId, RowIndex)>,
-        _: &[(TypeId, &'a Table)],
-    ) -> Self::Output<'a> {
-    }
+    fn data<'a>(_: &[(TypeId, &'a Table)], _: &'a SparseSets, _: Ticks) -> Self::Output<'a> {}
 }
 impl<E, T: 'static> QueryOne<E> for With<T> {
     type Output<'a> = ();
@@ -439,7 +690,13 @@ impl<E, T: 'static> QueryOne<E> for With<T> {
         table.1.has_column::<T>()
     }
 
-    fn data<'a>(_: &[(TypeId, &'a Table)]) -> Self::Output<'a> {}
+    fn data<'a>(
+        _: (TypeId, &'a Table),
+        _: RowIndex,
+        _: &'a SparseSets,
+        _: Ticks,
+    ) -> Self::Output<'a> {
+    }
 }
 
 pub struct Without<T>(PhantomData<T>);
@@ -450,11 +707,7 @@ impl<E, T: 'static> Query<E> for Without<T> {
         !table.1.has_column::<T>()
     }
 
-    fn data<'a>(
-        _: &HashMap<EntityId, (TypeId, RowIndex)>,
-        _: &[(TypeId, &'a Table)],
-    ) -> Self::Output<'a> {
-    }
+    fn data<'a>(_: &[(TypeId, &'a Table)], _: &'a SparseSets, _: Ticks) -> Self::Output<'a> {}
 }
 impl<E, T: 'static> QueryOne<E> for Without<T> {
     type Output<'a> = ();
@@ -463,31 +716,39 @@ impl<E, T: 'static> QueryOne<E> for Without<T> {
         !table.1.has_column::<T>()
     }
 
-    fn data<'a>(_: &[(TypeId, &'a Table)]) -> Self::Output<'a> {}
+    fn data<'a>(
+        _: (TypeId, &'a Table),
+        _: RowIndex,
+        _: &'a SparseSets,
+        _: Ticks,
+    ) -> Self::Output<'a> {
+    }
 }
 
 pub struct Is<T>(PhantomData<T>);
-impl<E, T: Archetype> Query<E> for Is<T> {
+impl<E, T: Archetypes> Query<E> for Is<T> {
     type Output<'a> = ();
 
     fn filter(table: &(TypeId, &Table)) -> bool {
-        table.0 == TypeId::of::<T>()
+        T::contains(table.0)
     }
 
-    fn data<'a>(
-        _: &HashMap<EntityId, (TypeId, RowIndex)>,
-        _: &[(TypeId, &'a Table)],
-    ) -> Self::Output<'a> {
-    }
+    fn data<'a>(_: &[(TypeId, &'a Table)], _: &'a SparseSets, _: Ticks) -> Self::Output<'a> {}
 }
-impl<E, T: Archetype> QueryOne<E> for Is<T> {
+impl<E, T: Archetypes> QueryOne<E> for Is<T> {
     type Output<'a> = ();
 
     fn filter(table: &(TypeId, &Table)) -> bool {
-        table.0 == TypeId::of::<T>()
+        T::contains(table.0)
     }
 
-    fn data<'a>(_: &[(TypeId, &'a Table)]) -> Self::Output<'a> {}
+    fn data<'a>(
+        _: (TypeId, &'a Table),
+        _: RowIndex,
+        _: &'a SparseSets,
+        _: Ticks,
+    ) -> Self::Output<'a> {
+    }
 }
 
 pub struct ColumnsOptional<'a, T> {
@@ -511,16 +772,21 @@ impl<'a, T> ColumnsOptional<'a, T> {
     }
 }
 
-impl<E, T: Archetype> Query<E> for Option<&'_ T> {
+impl<E, T: 'static> Query<E> for Option<&'_ T> {
     type Output<'a> = ColumnsOptional<'a, T>;
 
     fn filter(_: &(TypeId, &Table)) -> bool {
         true
     }
 
+    fn access(access: &mut Access) {
+        access.read::<T>()
+    }
+
     fn data<'a>(
-        _: &HashMap<EntityId, (TypeId, RowIndex)>,
         tables: &[(TypeId, &'a Table)],
+        _: &'a SparseSets,
+        _ticks: Ticks,
     ) -> Self::Output<'a> {
         tables
             .iter()
@@ -529,50 +795,199 @@ impl<E, T: Archetype> Query<E> for Option<&'_ T> {
     }
 }
 
+impl<E, T: 'static> QueryOne<E> for Option<&'_ T> {
+    type Output<'a> = Option<Ref<'a, T>>;
+
+    fn filter(_: &(TypeId, &Table)) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>()
+    }
+
+    fn data<'a>(
+        (_, table): (TypeId, &'a Table),
+        row: RowIndex,
+        _: &'a SparseSets,
+        _ticks: Ticks,
+    ) -> Self::Output<'a> {
+        filter_map_ref(table.column::<T>()?, |column| column.get(row.0 as usize))
+    }
+}
+
+/// Mutable borrows of a column in each table, `None` for the rows of tables
+/// without it. Like [`ColumnsMut`] rows are only marked changed when written to.
+pub struct ColumnsOptionalMut<'a, T> {
+    columns: Vec<Result<TickedColumn<'a, T>, usize>>,
+    tick: u32,
+}
+
+impl<'a, T> ColumnsOptionalMut<'a, T> {
+    pub fn iter(&self) -> impl Iterator<Item = Option<&T>> {
+        self.columns.iter().flat_map(|column| match column {
+            Ok((column, _)) => column.iter().map(Some).collect::<Vec<Option<&T>>>(),
+            Err(size) => vec![None; *size],
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = Option<Mut<'_, T>>> + use<'_, 'a, T> {
+        let tick = self.tick;
+        self.columns
+            .iter_mut()
+            .flat_map(move |column| match column {
+                Ok((column, ticks)) => column
+                    .iter_mut()
+                    .zip(ticks.iter())
+                    .map(|(value, ticks)| Some(Mut { value, ticks, tick }))
+                    .collect::<Vec<_>>(),
+                Err(size) => std::iter::repeat_with(|| None).take(*size).collect(),
+            })
+    }
+
+    pub fn for_each<F: FnMut(Option<Mut<'_, T>>)>(&mut self, f: F) {
+        self.iter_mut().for_each(f)
+    }
+}
+
+impl<E, T: 'static> Query<E> for Option<&'_ mut T> {
+    type Output<'a> = ColumnsOptionalMut<'a, T>;
+
+    fn filter(_: &(TypeId, &Table)) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        access.write::<T>()
+    }
+
+    fn data<'a>(
+        tables: &[(TypeId, &'a Table)],
+        _: &'a SparseSets,
+        ticks: Ticks,
+    ) -> Self::Output<'a> {
+        ColumnsOptionalMut {
+            columns: tables
+                .iter()
+                .map(|(_, table)| table.column_mut_ticked::<T>().ok_or_else(|| table.len()))
+                .collect(),
+            tick: ticks.this_run,
+        }
+    }
+}
+
+impl<E, T: 'static> QueryOne<E> for Option<&'_ mut T> {
+    type Output<'a> = Option<ComponentMut<'a, T>>;
+
+    fn filter(_: &(TypeId, &Table)) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        access.write::<T>()
+    }
+
+    fn data<'a>(
+        (_, table): (TypeId, &'a Table),
+        row: RowIndex,
+        _: &'a SparseSets,
+        ticks: Ticks,
+    ) -> Self::Output<'a> {
+        table.component_mut(row, ticks.this_run)
+    }
+}
+
 impl<E> Query<E> for EntityId {
-    type Output<'a> = Vec<EntityId>;
+    type Output<'a> = Columns<'a, EntityId>;
 
     fn filter(_: &(TypeId, &Table)) -> bool {
         true
     }
 
     fn data<'a>(
-        entities: &HashMap<EntityId, (TypeId, RowIndex)>,
         tables: &[(TypeId, &'a Table)],
+        _: &'a SparseSets,
+        _ticks: Ticks,
     ) -> Self::Output<'a> {
         tables
             .iter()
-            .flat_map(|(ty, table)| {
-                (0..table.len()).filter_map(|i| {
-                    entities
-                        .iter()
-                        .find(|(_, (t, row))| *t == *ty && i == row.0 as usize)
-                })
-            })
-            .map(|(id, _)| *id)
+            .map(|(_, table)| Ref::map(table.entities.borrow(), |entities| entities.as_slice()))
             .collect()
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-pub struct EntityId(u64);
+/// Why [`World::try_query_one`] couldn't return a single entity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QueryError {
+    NoMatch,
+    /// How many entities matched
+    MultipleMatches(usize),
+}
+
+impl std::fmt::Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NoMatch => write!(f, "No entity matched a query expecting one"),
+            Self::MultipleMatches(count) => {
+                write!(f, "{count} entities matched a query expecting one")
+            }
+        }
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+fn check_aliased(access: &Access) {
+    let aliased = access.aliased();
+    if !aliased.is_empty() {
+        panic!("Query has aliased mutable access to {}", aliased.join(", "));
+    }
+}
+
+thread_local! {
+    /// When the system running on this thread last ran, `None` outside systems
+    static SYSTEM_LAST_RUN: std::cell::Cell<Option<u32>> = const { std::cell::Cell::new(None) };
+}
 
 pub struct World<E> {
-    next_id: Cell<u64>,
-    entities: RefCell<HashMap<EntityId, (TypeId, RowIndex)>>,
-    archetypes: HashMap<TypeId, Table>,
-    systems: Vec<Rc<dyn System<E>>>,
-    resources: HashMap<TypeId, Rc<RefCell<dyn Any>>>,
+    entities: Mutex<Entities>,
+    tables: Tables,
+    archetypes: HashMap<TypeId, TableId>,
+    signatures: Mutex<HashMap<(TypeId, Vec<TypeId>), TableId>>,
+    systems: Vec<Scheduled<E>>,
+    order: RefCell<Option<Vec<usize>>>,
+    resources: HashMap<TypeId, Shared<RefCell<DynResource>>>,
+    resource_ticks: HashMap<TypeId, Cell<u32>>,
+    commands: Mutex<Vec<Command<E>>>,
+    change_tick: Cell<u32>,
+    last_run: Cell<u32>,
+    event_updates: Vec<fn(&World<E>)>,
+    migrations: HashMap<(TypeId, u32), Migration>,
+    prefabs: HashMap<String, Prefab>,
+    /// Components inserted outside of archetypes, for [`reflect`]
+    inserted: Mutex<HashMap<TypeId, Component>>,
+    sparse: SparseSets,
 }
 
 impl<E> Default for World<E> {
     fn default() -> Self {
         Self {
-            next_id: Cell::new(0),
-            entities: RefCell::new(HashMap::new()),
+            entities: Mutex::new(Entities::default()),
+            tables: Tables::default(),
             archetypes: HashMap::new(),
+            signatures: Mutex::new(HashMap::new()),
             systems: Vec::new(),
+            order: RefCell::new(None),
             resources: HashMap::new(),
+            resource_ticks: HashMap::new(),
+            commands: Mutex::new(Vec::new()),
+            change_tick: Cell::new(1),
+            last_run: Cell::new(0),
+            event_updates: Vec::new(),
+            migrations: HashMap::new(),
+            prefabs: HashMap::new(),
+            inserted: Mutex::new(HashMap::new()),
+            sparse: SparseSets::default(),
         }
     }
 }
@@ -586,120 +1001,463 @@ impl<E> World<E> {
         f(self)
     }
 
-    pub fn with_system<T: System<E> + 'static>(mut self, system: T) -> Self {
-        self.systems.push(Rc::new(system));
+    fn add_system(mut self, system: Shared<dyn System<E>>, name: &'static str) -> Self {
+        self.systems.push(Scheduled::new(system, name));
+        *self.order.get_mut() = None;
         self
     }
 
-    pub fn with_system_mut<T: SystemMut<E> + 'static>(mut self, system: T) -> Self {
-        self.systems.push(Rc::new(RefCell::new(system)));
-        self
+    pub fn with_system<T: System<E> + 'static>(self, system: T) -> Self {
+        self.add_system(Shared::new(system), type_name::<T>())
     }
 
-    pub fn with_handler<T: Fn(&World<E>, &E) + 'static>(mut self, handler: T) -> Self {
-        self.systems.push(Rc::new(Handler(handler)));
-        self
+    pub fn with_system_mut<T: SystemMut<E> + MaybeSync + 'static>(self, system: T) -> Self {
+        self.add_system(Shared::new(RefCell::new(system)), type_name::<T>())
+    }
+
+    pub fn with_handler<T: Fn(&World<E>, &E) + MaybeSync + 'static>(self, handler: T) -> Self {
+        self.add_system(Shared::new(Handler(handler)), type_name::<T>())
+    }
+
+    pub fn with_ticker<T: Fn(&World<E>) + MaybeSync + 'static>(self, ticker: T) -> Self {
+        self.add_system(Shared::new(Ticker(ticker)), type_name::<T>())
     }
 
-    pub fn with_ticker<T: Fn(&World<E>) + 'static>(mut self, ticker: T) -> Self {
-        self.systems.push(Rc::new(Ticker(ticker)));
+    /// Adds a function system, its parameters are fetched from the world each
+    /// tick and its access is declared from their types, see [`system`]
+    pub fn with_fn_system<P, F: SystemFn<E, P>>(self, f: F) -> Self {
+        let mut access = Access::default();
+        F::access(&mut access);
+        let mut resources = Vec::new();
+        F::resources(&mut resources);
+
+        self.with_ticker(move |world| f.run(world))
+            .configure(|system| {
+                system.name = type_name::<F>();
+                system.access = Some(access);
+                system.resources = resources;
+                system.function = true;
+            })
+    }
+
+    fn configure<F: FnOnce(&mut Scheduled<E>)>(mut self, f: F) -> Self {
+        f(self
+            .systems
+            .last_mut()
+            .expect("No system has been added to configure"));
+        *self.order.get_mut() = None;
         self
     }
 
-    pub fn with_resource<T: Any>(mut self, resource: T) -> Self {
+    /// Moves the most recently added system into `stage`, systems start in
+    /// [`Stage::Update`].
+    pub fn in_stage(self, stage: Stage) -> Self {
+        self.configure(|system| system.stage = stage)
+    }
+
+    /// Labels the most recently added system so other systems can be ordered
+    /// relative to it.
+    pub fn label(self, label: &'static str) -> Self {
+        self.configure(|system| system.labels.push(label))
+    }
+
+    /// Runs the most recently added system before every system with `label`
+    pub fn before(self, label: &'static str) -> Self {
+        self.configure(|system| system.before.push(label))
+    }
+
+    /// Runs the most recently added system after every system with `label`
+    pub fn after(self, label: &'static str) -> Self {
+        self.configure(|system| system.after.push(label))
+    }
+
+    /// Only runs the most recently added system, for both ticks and events,
+    /// while `condition` holds.
+    pub fn run_if<C: Fn(&World<E>) -> bool + MaybeSync + 'static>(self, condition: C) -> Self {
+        self.configure(|system| system.conditions.push(Shared::new(condition)))
+    }
+
+    /// Declares that the most recently added system reads the component `T`.
+    /// Systems that declare their access can run alongside each other in
+    /// [`World::tick_parallel`], systems that don't run alone.
+    pub fn reads<T: 'static>(self) -> Self {
+        self.declare(Access::read::<T>)
+    }
+
+    /// Declares that the most recently added system writes the component
+    /// `T`, see [`World::reads`]
+    pub fn writes<T: 'static>(self) -> Self {
+        self.declare(Access::write::<T>)
+    }
+
+    /// Declares that the most recently added system reads the resource `T`,
+    /// see [`World::reads`]
+    pub fn reads_resource<T: 'static>(self) -> Self {
+        self.declare(Access::read_resource::<T>)
+    }
+
+    /// Declares that the most recently added system writes the resource `T`,
+    /// see [`World::reads`]
+    pub fn writes_resource<T: 'static>(self) -> Self {
+        self.declare(Access::write_resource::<T>)
+    }
+
+    fn declare(self, f: fn(&mut Access)) -> Self {
+        self.configure(|system| f(system.access.get_or_insert_with(Access::default)))
+    }
+
+    pub fn with_resource<T: Any + MaybeSync>(mut self, resource: T) -> Self {
         self.resources
-            .insert(TypeId::of::<T>(), Rc::new(RefCell::new(resource)));
+            .insert(TypeId::of::<T>(), Shared::new(RefCell::new(resource)));
+        self.resource_ticks
+            .insert(TypeId::of::<T>(), Cell::new(self.change_tick.get()));
         self
     }
 
-    pub fn register<T: Archetype + Serialize + for<'a> Deserialize<'a> + Clone>(mut self) -> Self {
-        self.archetypes.insert(TypeId::of::<T>(), Table::new::<T>());
+    /// Registers an [`Events`] queue for `T`, updated at the end of each tick
+    pub fn with_event<T: MaybeSync + 'static>(mut self) -> Self {
+        if self.resources.contains_key(&TypeId::of::<Events<T>>()) {
+            return self;
+        }
+        self.event_updates
+            .push(|world| world.get_mut::<Events<T>>().unwrap().update());
+        self.with_resource(Events::<T>::default())
+    }
+
+    /// Registers an [`Events`] queue for `T` that is also fed by submitted
+    /// events `f` maps to a `T`, so readers can move over to typed events
+    /// while senders still use [`World::submit`].
+    pub fn with_event_from<T: MaybeSync + 'static, F: Fn(&E) -> Option<T> + MaybeSync + 'static>(
+        self,
+        f: F,
+    ) -> Self {
+        self.with_event::<T>().with_handler(move |world, event| {
+            if let Some(event) = f(event) {
+                world.send(event)
+            }
+        })
+    }
+
+    fn register_table<T: Archetype>(mut self, table: Table) -> Self {
+        if self.archetype_table(T::name()).is_some() {
+            panic!("Archetype name {} is already registered", T::name());
+        }
+        let mut signature = table.column_types().collect::<Vec<_>>();
+        signature.sort();
+        // Caught by the derive unless the types are only equal through
+        // aliases or generics
+        if signature.windows(2).any(|pair| pair[0] == pair[1]) {
+            panic!(
+                "Archetype {} has two components of the same type",
+                T::name()
+            );
+        }
+        let id = self.tables.push(table);
+        self.archetypes.insert(TypeId::of::<T>(), id);
+        self.signatures
+            .get_mut()
+            .insert((TypeId::of::<T>(), signature), id);
         self
     }
 
-    pub fn register_unsaved<T: Archetype>(mut self) -> Self {
+    pub fn register<T: Archetype + Serialize + for<'a> Deserialize<'a> + Clone>(self) -> Self {
+        self.register_table::<T>(Table::new::<T>())
+    }
+
+    pub fn register_unsaved<T: Archetype>(self) -> Self {
+        self.register_table::<T>(Table::new_unsaved::<T>())
+    }
+
+    /// The table of the registered archetype with the given name
+    pub(crate) fn archetype_table(&self, name: &str) -> Option<TableId> {
         self.archetypes
-            .insert(TypeId::of::<T>(), Table::new_unsaved::<T>());
-        self
+            .values()
+            .copied()
+            .find(|id| self.tables.get(*id).name == name)
     }
 
     pub fn spawn<T: Archetype>(&self, entity: T) -> EntityId {
-        if !self.archetypes.contains_key(&TypeId::of::<T>()) {
+        let id = self.entities.lock().reserve();
+        self.spawn_at(id, entity);
+        id
+    }
+
+    /// Spawns into an id from [`Entities::reserve`], dropping the entity if
+    /// the id has been given back or spawned since
+    fn spawn_at<T: Archetype>(&self, id: EntityId, entity: T) {
+        let Some(&table_id) = self.archetypes.get(&TypeId::of::<T>()) else {
             panic!("Unregistered archetype {}", std::any::type_name::<T>());
+        };
+        if !self.entities.lock().is_reserved(id) {
+            return;
         }
 
-        let store = self.archetypes.get(&TypeId::of::<T>()).unwrap();
-        entity.add(store);
-        self.entities.borrow_mut().insert(
-            EntityId(self.next_id.get()),
-            (TypeId::of::<T>(), RowIndex(store.len() as u32 - 1)),
-        );
-        self.next_id.set(self.next_id.get() + 1);
-        EntityId(self.next_id.get() - 1)
+        let store = self.tables.get(table_id);
+        let row = entity.add(store, id, self.change_tick.get());
+        self.entities.lock().set(id, (table_id, row));
     }
 
-    pub fn despawn<T: Archetype + 'static>(&self, entity: EntityId) {
-        let mut entities = self.entities.borrow_mut();
-        let Some((table_id, row)) = entities.remove(&entity) else {
-            return;
-        };
+    pub fn commands(&self) -> Commands<'_, E> {
+        Commands::new(self)
+    }
 
-        if table_id != TypeId::of::<T>() {
-            panic!("Despawn archetype mismatch")
+    pub fn apply_commands(&self) {
+        loop {
+            let commands = std::mem::take(&mut *self.commands.lock());
+            if commands.is_empty() {
+                break;
+            }
+            commands.into_iter().for_each(|command| command(self));
         }
+    }
 
-        let Some(table) = self.archetypes.get(&table_id) else {
-            return;
-        };
-        T::remove(table, row);
-        entities
-            .values_mut()
-            .find(|(t, r)| t == &table_id && r.0 == table.len() as u32)
-            .map(|(_, r)| *r = row);
+    /// Despawns an entity, detaching it from its parent and leaving its
+    /// children as roots, see [`World::despawn_recursive`]
+    pub fn despawn(&self, entity: EntityId) -> Result<(), EntityError> {
+        self.unlink(entity);
+        let mut entities = self.entities.lock();
+        let (table_id, row) = entities.free(entity).ok_or(EntityError::Dead(entity))?;
+        self.sparse.despawn(entity);
+
+        if let Some(moved) = self.tables.get(table_id).remove_row(row) {
+            entities.set(moved, (table_id, row));
+        }
+        Ok(())
+    }
+
+    pub fn is_alive(&self, entity: EntityId) -> bool {
+        self.entities.lock().is_alive(entity)
+    }
+
+    /// Finds or creates the table for entities of the given archetype whose
+    /// components are exactly `signature`, tables only inherit serialization
+    /// while they still contain every component of the archetype.
+    fn table_with(
+        &self,
+        archetype: TypeId,
+        mut signature: Vec<TypeId>,
+        columns: impl FnOnce() -> Vec<Column>,
+    ) -> TableId {
+        signature.sort();
+        // Held while the table is created so two threads can't both create it
+        let mut signatures = self.signatures.lock();
+        if let Some(id) = signatures.get(&(archetype, signature.clone())) {
+            return *id;
+        }
+
+        let base = self.tables.get(self.archetypes[&archetype]);
+        let mut table = Table::from_columns(archetype, columns());
+        table.name = base.name;
+        table.version = base.version;
+        if base.column_types().all(|ty| signature.contains(&ty)) {
+            table.serialize = base.serialize;
+        }
+
+        let id = self.tables.push(table);
+        signatures.insert((archetype, signature), id);
+        id
+    }
+
+    fn migrate(&self, entity: EntityId, to: TableId) -> (RowIndex, Vec<Column>) {
+        let mut entities = self.entities.lock();
+        let (from, row) = entities.get(entity).unwrap();
+        let (new_row, moved, rest) = self.tables.get(from).move_row(row, self.tables.get(to));
+        if let Some(moved) = moved {
+            entities.set(moved, (from, row));
+        }
+        entities.set(entity, (to, new_row));
+        (new_row, rest)
+    }
+
+    /// Adds a component to an entity, moving it to the table with the extra
+    /// column unless `T` is stored sparsely. If the entity already has a `T`
+    /// it is replaced.
+    pub fn insert_component<T: MaybeSync + 'static>(
+        &self,
+        entity: EntityId,
+        component: T,
+    ) -> Result<(), EntityError> {
+        let (table_id, row) = self
+            .entities
+            .lock()
+            .get(entity)
+            .ok_or(EntityError::Dead(entity))?;
+        if let Some(set) = self.sparse.get::<T>() {
+            set.insert(entity, component, self.change_tick.get());
+            return Ok(());
+        }
+        let table = self.tables.get(table_id);
+
+        if let Some(mut value) = table.component_mut::<T>(row, self.change_tick.get()) {
+            *value = component;
+            return Ok(());
+        }
+
+        let to = self.table_with(
+            table.archetype,
+            table
+                .column_types()
+                .chain(std::iter::once(TypeId::of::<T>()))
+                .collect(),
+            || {
+                table
+                    .columns()
+                    .map(|column| Column {
+                        data: column.data.new_like().unwrap(),
+                    })
+                    .chain(std::iter::once(Column::new::<T>()))
+                    .collect()
+            },
+        );
+        self.inserted
+            .lock()
+            .entry(TypeId::of::<T>())
+            .or_insert_with(Component::inserted::<T>);
+        self.migrate(entity, to);
+        self.tables.get(to).push(component, self.change_tick.get());
+        Ok(())
+    }
+
+    /// Removes a component from an entity, moving it to the table without
+    /// that column unless `T` is stored sparsely.
+    pub fn remove_component<T: MaybeSync + 'static>(
+        &self,
+        entity: EntityId,
+    ) -> Result<T, EntityError> {
+        let (table_id, _) = self
+            .entities
+            .lock()
+            .get(entity)
+            .ok_or(EntityError::Dead(entity))?;
+        if let Some(set) = self.sparse.get::<T>() {
+            return set
+                .remove(entity)
+                .ok_or(EntityError::MissingComponent(entity));
+        }
+        let table = self.tables.get(table_id);
+
+        if !table.has_column::<T>() {
+            return Err(EntityError::MissingComponent(entity));
+        }
+
+        let to = self.table_with(
+            table.archetype,
+            table
+                .column_types()
+                .filter(|ty| *ty != TypeId::of::<T>())
+                .collect(),
+            || {
+                table
+                    .columns()
+                    .filter(|column| column.data.ty() != TypeId::of::<T>())
+                    .map(|column| Column {
+                        data: column.data.new_like().unwrap(),
+                    })
+                    .collect()
+            },
+        );
+        let (_, mut rest) = self.migrate(entity, to);
+        Ok(rest.pop().unwrap().data.pop::<T>().unwrap())
+    }
+
+    /// Change ticks of the system currently running, outside of systems
+    /// `last_run` is just before the start of the last tick.
+    pub fn ticks(&self) -> Ticks {
+        Ticks {
+            last_run: self.last_run(),
+            this_run: self.change_tick.get(),
+        }
+    }
+
+    /// Every table passing a query's `filter`, with the archetype it belongs to
+    pub(crate) fn matching<F: Fn(&(TypeId, &Table)) -> bool>(
+        &self,
+        filter: F,
+    ) -> Vec<(TypeId, &Table)> {
+        self.tables
+            .iter()
+            .map(|table| (table.archetype, table))
+            .filter(filter)
+            .collect()
     }
 
     pub fn query<Q: Query<E>>(&self) -> Q::Output<'_> {
-        Q::data(
-            &self.entities.borrow(),
-            &self
-                .archetypes
-                .iter()
-                .map(|(&ty, table)| (ty, table))
-                .filter(Q::filter)
-                .collect::<Vec<_>>(),
-        )
+        let mut access = Access::default();
+        Q::access(&mut access);
+        check_aliased(&access);
+
+        Q::data(&self.matching(Q::filter), &self.sparse, self.ticks())
+    }
+
+    /// Tables passing `filter` that aren't empty, as long as they hold exactly
+    /// one entity between them
+    pub(crate) fn matching_one<F, Q: QueryOne<F>>(
+        &self,
+    ) -> Result<((TypeId, &Table), RowIndex), QueryError> {
+        let ticks = self.ticks();
+        let mut rows = self.matching(Q::filter).into_iter().flat_map(|table| {
+            (0..table.1.len() as u32)
+                .map(RowIndex)
+                .filter(move |row| Q::matches(table, *row, &self.sparse, ticks))
+                .map(move |row| (table, row))
+        });
+        let first = rows.next().ok_or(QueryError::NoMatch)?;
+        match rows.count() {
+            0 => Ok(first),
+            count => Err(QueryError::MultipleMatches(count + 1)),
+        }
+    }
+
+    /// The first row of the first table `Q` matches, whether or not it passes
+    /// the query's row filters
+    pub(crate) fn first_row<F, Q: QueryOne<F>>(&self) -> ((TypeId, &Table), RowIndex) {
+        let table = self
+            .matching(Q::filter)
+            .into_iter()
+            .find(|(_, table)| !table.is_empty())
+            .unwrap_or_else(|| panic!("{}", QueryError::NoMatch));
+        (table, RowIndex(0))
     }
 
+    /// Panics when no entity matches, see [`World::try_query_one`]
     pub fn query_one<Q: QueryOne<E>>(&self) -> Q::Output<'_> {
-        Q::data(
-            &self
-                .archetypes
-                .iter()
-                .map(|(&ty, table)| (ty, table))
-                .filter(Q::filter)
-                .collect::<Vec<_>>(),
-        )
+        let mut access = Access::default();
+        Q::access(&mut access);
+        check_aliased(&access);
+
+        let (table, row) = self.first_row::<E, Q>();
+        Q::data(table, row, &self.sparse, self.ticks())
+    }
+
+    /// Like [`World::query_one`], but returns an error instead of panicking
+    /// when no entity or more than one matches. Rows rejected by filters like
+    /// `Changed` don't count as matching.
+    pub fn try_query_one<Q: QueryOne<E>>(&self) -> Result<Q::Output<'_>, QueryError> {
+        let mut access = Access::default();
+        Q::access(&mut access);
+        check_aliased(&access);
+
+        let (table, row) = self.matching_one::<E, Q>()?;
+        Ok(Q::data(table, row, &self.sparse, self.ticks()))
     }
 
     pub fn get_component<T: 'static>(&self, id: EntityId) -> Option<Ref<'_, T>> {
-        let (table, row) = self.entities.borrow().get(&id).copied()?;
-        let table = self
-            .archetypes
-            .get(&table)
-            .expect("Using unregistered archetype");
-        Ref::filter_map(table.column::<T>()?, |column| column.get(row.0 as usize)).ok()
+        if let Some(set) = self.sparse.get::<T>() {
+            return set.get(id);
+        }
+        let (table, row) = self.entities.lock().get(id)?;
+        let table = self.tables.get(table);
+        filter_map_ref(table.column::<T>()?, |column| column.get(row.0 as usize))
     }
 
-    pub fn get_component_mut<T: 'static>(&self, id: EntityId) -> Option<RefMut<'_, T>> {
-        let (table, row) = self.entities.borrow().get(&id).copied()?;
-        let table = self
-            .archetypes
-            .get(&table)
-            .expect("Using unregistered archetype");
-        RefMut::filter_map(table.column_mut::<T>()?, |column| {
-            column.get_mut(row.0 as usize)
-        })
-        .ok()
+    pub fn get_component_mut<T: 'static>(&self, id: EntityId) -> Option<ComponentMut<'_, T>> {
+        if let Some(set) = self.sparse.get::<T>() {
+            return set.get_mut(id, self.change_tick.get());
+        }
+        let (table, row) = self.entities.lock().get(id)?;
+        self.tables
+            .get(table)
+            .component_mut(row, self.change_tick.get())
     }
 
     pub fn get<T: Any>(&self) -> Option<Ref<'_, T>> {
@@ -708,31 +1466,432 @@ impl<E> World<E> {
             .map(|resource| Ref::map(resource.borrow(), |x| x.downcast_ref().unwrap()))
     }
 
+    pub fn events<T: 'static>(&self) -> Option<Ref<'_, Events<T>>> {
+        self.get::<Events<T>>()
+    }
+
+    pub fn event_writer<T: 'static>(&self) -> Option<EventWriter<'_, T>> {
+        self.get_mut::<Events<T>>()
+            .map(|events| EventWriter { events })
+    }
+
+    /// Sends an event to the registered [`Events`] queue for `T`
+    pub fn send<T: 'static>(&self, event: T) {
+        self.event_writer()
+            .unwrap_or_else(|| panic!("Unregistered event {}", type_name::<T>()))
+            .send(event)
+    }
+
+    /// Mutably borrows a resource, marking it changed
     pub fn get_mut<T: Any>(&self) -> Option<RefMut<'_, T>> {
-        self.resources
+        let resource = self.resources.get(&TypeId::of::<T>())?;
+        self.resource_ticks[&TypeId::of::<T>()].set(self.change_tick.get());
+        Some(RefMut::map(resource.borrow_mut(), |x| {
+            x.downcast_mut().unwrap()
+        }))
+    }
+
+    /// The tick the resource was added or last mutably borrowed at
+    pub fn resource_tick<T: Any>(&self) -> Option<u32> {
+        self.resource_ticks
             .get(&TypeId::of::<T>())
-            .map(|resource| RefMut::map(resource.borrow_mut(), |x| x.downcast_mut().unwrap()))
+            .map(|tick| tick.get())
+    }
+
+    /// Whether the resource has been mutably borrowed since the running
+    /// system last ran
+    pub fn resource_changed<T: Any>(&self) -> bool {
+        self.resource_tick::<T>()
+            .is_some_and(|tick| tick > self.last_run())
+    }
+
+    fn last_run(&self) -> u32 {
+        SYSTEM_LAST_RUN.get().unwrap_or_else(|| self.last_run.get())
     }
 
     pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.resource_ticks.remove(&TypeId::of::<T>());
         self.resources.remove(&TypeId::of::<T>()).and_then(|rc| {
-            let ptr: *const RefCell<dyn Any> = Rc::into_raw(rc);
+            let ptr: *const RefCell<DynResource> = Shared::into_raw(rc);
             let ptr: *const RefCell<T> = ptr.cast();
-            unsafe { Rc::into_inner(Rc::from_raw(ptr)).map(|x| x.into_inner()) }
+            unsafe { Shared::into_inner(Shared::from_raw(ptr)).map(|x| x.into_inner()) }
         })
     }
 
-    pub fn tick(&self) {
-        self.systems
-            .clone()
+    /// Checks function systems have the resources they need and don't borrow
+    /// anything mutably twice, then orders the systems by stage and their
+    /// ordering constraints and checks conflicting function systems are
+    /// ordered relative to each other. This is done on the first tick anyway but lets
+    /// problems be reported as errors, resources can be added after systems
+    /// so they are only checked here.
+    pub fn schedule(&self) -> Result<(), ScheduleError> {
+        if self.order.borrow().is_none() {
+            self.systems
+                .iter()
+                .try_for_each(|system| system.validate(|ty| self.resources.contains_key(ty)))?;
+            let order = schedule::order(&self.systems)?;
+            schedule::conflicts(&self.systems)?;
+            *self.order.borrow_mut() = Some(order);
+        }
+        Ok(())
+    }
+
+    fn order(&self) -> Vec<usize> {
+        if let Err(e) = self.schedule() {
+            panic!("{e}");
+        }
+        self.order.borrow().clone().unwrap()
+    }
+
+    /// Runs `f` on each system in order whose run conditions hold, with the
+    /// change ticks set up for the system.
+    fn run_systems<F: Fn(&dyn System<E>)>(&self, f: F) {
+        self.order()
             .into_iter()
-            .for_each(|system| system.tick(self))
+            .map(|i| &self.systems[i])
+            .for_each(|system| {
+                if system.with_last_run(|| system.should_run(self)) {
+                    system.run(self.change_tick.get(), &f);
+                    self.change_tick.set(self.change_tick.get() + 1);
+                }
+            });
+    }
+
+    /// Applies commands and updates events once the systems have run
+    fn end_tick(&self, start: u32) {
+        self.apply_commands();
+        self.event_updates.iter().for_each(|update| update(self));
+        self.last_run.set(start - 1);
+        self.change_tick.set(self.change_tick.get() + 1);
+    }
+
+    pub fn tick(&self) {
+        let start = self.change_tick.get();
+        self.run_systems(|system| system.tick(self));
+        self.end_tick(start);
     }
 
     pub fn submit(&self, event: E) {
-        self.systems
-            .clone()
+        self.run_systems(|system| system.event(self, &event))
+    }
+}
+
+#[cfg(feature = "parallel")]
+impl<E> World<E> {
+    /// Like [`World::tick`], but runs systems that declared non-conflicting
+    /// access with [`World::reads`] and [`World::writes`] at the same time on
+    /// the rayon thread pool. Systems still run after everything before them
+    /// in the schedule that they conflict with or are ordered after.
+    pub fn tick_parallel(&self) {
+        let start = self.change_tick.get();
+        schedule::batches(&self.systems, &self.order())
             .into_iter()
-            .for_each(|system| system.event(self, &event))
+            .for_each(|batch| {
+                let this_run = self.change_tick.get();
+                let batch = batch
+                    .into_iter()
+                    .map(|i| &self.systems[i])
+                    .filter(|system| system.with_last_run(|| system.should_run(self)))
+                    .collect::<Vec<_>>();
+
+                rayon::scope(|scope| {
+                    batch.into_iter().for_each(|system| {
+                        scope.spawn(move |_| system.run(this_run, &|system| system.tick(self)))
+                    })
+                });
+                self.change_tick.set(self.change_tick.get() + 1);
+            });
+        self.end_tick(start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tecs_derive::{Archetype, Bundle};
+
+    #[derive(Archetype, Clone, Debug, PartialEq)]
+    struct Thing {
+        value: u32,
+    }
+
+    #[derive(Archetype, Clone, Debug, PartialEq)]
+    struct Tagged(u32, &'static str);
+
+    #[derive(Archetype, Clone, Debug, PartialEq)]
+    struct Cached {
+        value: u32,
+        #[archetype(skip)]
+        cache: Option<String>,
+    }
+
+    #[derive(Archetype, Clone, Debug, PartialEq)]
+    #[archetype(name = "Pair")]
+    struct Pair<A, B> {
+        a: A,
+        b: B,
+    }
+
+    #[test]
+    pub fn test_derive() {
+        let world = World::<()>::new()
+            .register_unsaved::<Tagged>()
+            .register_unsaved::<Cached>()
+            .register_unsaved::<Pair<f32, bool>>();
+        let tagged = world.spawn(Tagged(1, "a"));
+        let cached = world.spawn(Cached {
+            value: 2,
+            cache: Some(String::from("cached")),
+        });
+        let pair = world.spawn(Pair {
+            a: 0.5_f32,
+            b: true,
+        });
+
+        assert_eq!(world.get_component::<&str>(tagged).as_deref(), Some(&"a"));
+        assert_eq!(world.get_component::<u32>(cached).as_deref(), Some(&2));
+        assert!(world.get_component::<Option<String>>(cached).is_none());
+        assert_eq!(world.get_component::<bool>(pair).as_deref(), Some(&true));
+
+        let (table, row) = world.entities.lock().get(cached).unwrap();
+        assert_eq!(
+            Cached::get(world.tables.get(table), row),
+            Cached {
+                value: 2,
+                cache: None
+            }
+        );
+    }
+
+    #[derive(Bundle, Clone, Debug, PartialEq)]
+    struct Body {
+        position: (f32, f32),
+        #[archetype(flatten)]
+        shape: Shape,
+    }
+
+    #[derive(Bundle, Clone, Debug, PartialEq)]
+    struct Shape(f32, #[archetype(skip)] Option<String>);
+
+    #[derive(Archetype, Clone, Debug, PartialEq)]
+    struct Ball {
+        #[archetype(flatten)]
+        body: Body,
+        bounces: u32,
+    }
+
+    #[test]
+    pub fn test_bundle() {
+        let world = World::<()>::new().register_unsaved::<Ball>();
+        let body = Body {
+            position: (1.0, 2.0),
+            shape: Shape(0.5, Some(String::from("circle"))),
+        };
+        let ball = world.spawn(Ball {
+            body: body.clone(),
+            bounces: 3,
+        });
+        assert_eq!(Ball::columns().len(), 3);
+
+        let (positions, radii) = world.query::<(&(f32, f32), &f32)>();
+        assert_eq!(positions.iter().collect::<Vec<_>>(), vec![&(1.0, 2.0)]);
+        assert_eq!(radii.iter().collect::<Vec<_>>(), vec![&0.5]);
+
+        let (table, row) = world.entities.lock().get(ball).unwrap();
+        assert_eq!(
+            Ball::get(world.tables.get(table), row),
+            Ball {
+                body: Body {
+                    shape: Shape(0.5, None),
+                    ..body
+                },
+                bounces: 3
+            }
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Archetype Pair has two components of the same type")]
+    pub fn test_duplicate_component() {
+        let _ = World::<()>::new().register_unsaved::<Pair<u32, u32>>();
+    }
+
+    #[test]
+    pub fn test_despawn_swap_remove() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let a = world.spawn(Thing { value: 0 });
+        let b = world.spawn(Thing { value: 1 });
+        let c = world.spawn(Thing { value: 2 });
+
+        assert_eq!(world.despawn(a), Ok(()));
+        assert!(!world.is_alive(a));
+        assert_eq!(world.despawn(a), Err(EntityError::Dead(a)));
+        assert!(world.get_component::<u32>(a).is_none());
+        assert_eq!(*world.get_component::<u32>(b).unwrap(), 1);
+        assert_eq!(*world.get_component::<u32>(c).unwrap(), 2);
+
+        let d = world.spawn(Thing { value: 3 });
+        assert_eq!(d.index(), a.index());
+        assert!(world.get_component::<u32>(a).is_none());
+        assert_eq!(*world.get_component::<u32>(d).unwrap(), 3);
+    }
+
+    #[test]
+    pub fn test_insert_remove_component() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let a = world.spawn(Thing { value: 0 });
+        let b = world.spawn(Thing { value: 1 });
+        let c = world.spawn(Thing { value: 2 });
+
+        world.insert_component(a, String::from("a")).unwrap();
+        world.insert_component(b, String::from("b")).unwrap();
+        world.insert_component(b, String::from("B")).unwrap();
+        assert_eq!(*world.get_component::<String>(a).unwrap(), "a");
+        assert_eq!(*world.get_component::<String>(b).unwrap(), "B");
+        assert!(world.get_component::<String>(c).is_none());
+        assert_eq!(*world.get_component::<u32>(a).unwrap(), 0);
+        assert_eq!(*world.get_component::<u32>(c).unwrap(), 2);
+
+        let (values, _) = world.query::<(&u32, Is<Thing>)>();
+        assert_eq!(values.iter().count(), 3);
+        drop(values);
+
+        assert_eq!(world.remove_component::<String>(a), Ok(String::from("a")));
+        assert_eq!(
+            world.remove_component::<String>(a),
+            Err(EntityError::MissingComponent(a))
+        );
+        assert_eq!(world.remove_component::<u32>(b), Ok(1));
+        assert!(world.get_component::<u32>(b).is_none());
+        assert_eq!(*world.get_component::<String>(b).unwrap(), "B");
+        assert_eq!(*world.get_component::<u32>(a).unwrap(), 0);
+
+        world.despawn(b).unwrap();
+        assert_eq!(world.insert_component(b, 0_u8), Err(EntityError::Dead(b)));
+    }
+
+    #[test]
+    pub fn test_component_drops() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+        #[derive(Clone)]
+        struct Counted(String);
+
+        impl Drop for Counted {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        #[derive(Clone, Debug, PartialEq)]
+        struct Marker;
+
+        #[derive(Archetype, Clone)]
+        struct Named {
+            name: Counted,
+            marker: Marker,
+        }
+
+        let world = World::<()>::new().register_unsaved::<Named>();
+        let ids = ["a", "b", "c"]
+            .map(|name| {
+                world.spawn(Named {
+                    name: Counted(String::from(name)),
+                    marker: Marker,
+                })
+            })
+            .to_vec();
+        // Moving between tables and removing markers doesn't drop anything
+        world.insert_component(ids[0], 0_u32).unwrap();
+        assert_eq!(world.remove_component::<Marker>(ids[0]), Ok(Marker));
+        assert_eq!(world.get_component::<Counted>(ids[0]).unwrap().0, "a");
+        assert_eq!(DROPS.load(Ordering::SeqCst), 0);
+
+        world.despawn(ids[1]).unwrap();
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        drop(world.remove_component::<Counted>(ids[2]).unwrap());
+        assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+
+        drop(world);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    pub fn test_query_entities() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let ids = (0..4)
+            .map(|value| world.spawn(Thing { value }))
+            .collect::<Vec<_>>();
+        world.despawn(ids[1]).unwrap();
+
+        let (entities, values) = world.query::<(EntityId, &u32)>();
+        let rows = entities
+            .iter()
+            .copied()
+            .zip(values.iter().copied())
+            .collect::<Vec<_>>();
+        assert_eq!(rows, vec![(ids[0], 0), (ids[3], 3), (ids[2], 2)]);
+    }
+
+    #[test]
+    pub fn test_try_query_one() {
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .register_unsaved::<Cached>();
+        assert_eq!(
+            world.try_query_one::<&u32>().err(),
+            Some(QueryError::NoMatch)
+        );
+
+        let thing = world.spawn(Thing { value: 1 });
+        let other = world.spawn(Thing { value: 2 });
+        world.spawn(Cached {
+            value: 3,
+            cache: None,
+        });
+        assert_eq!(
+            world.try_query_one::<&u32>().err(),
+            Some(QueryError::MultipleMatches(3))
+        );
+        assert_eq!(
+            world.try_query_one::<(&u32, Is<Thing>)>().err(),
+            Some(QueryError::MultipleMatches(2))
+        );
+
+        let (value, _) = world.try_query_one::<(&u32, Is<Cached>)>().unwrap();
+        assert_eq!(*value, 3);
+        drop(value);
+
+        // Tables left empty by entities moving out don't count
+        world.insert_component(thing, 0.5_f32).unwrap();
+        world.despawn(other).unwrap();
+        let (mut value, _) = world.try_query_one::<(&mut u32, Is<Thing>)>().unwrap();
+        *value += 10;
+        drop(value);
+        assert_eq!(world.get_component::<u32>(thing).as_deref(), Some(&11));
+    }
+
+    #[test]
+    pub fn test_try_query_one_changed() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let thing = world.spawn(Thing { value: 1 });
+        world.spawn(Thing { value: 2 });
+        assert_eq!(
+            world.try_query_one::<(&u32, Changed<u32>)>().err(),
+            Some(QueryError::MultipleMatches(2))
+        );
+
+        world.tick();
+        world.tick();
+        assert_eq!(
+            world.try_query_one::<(&u32, Changed<u32>)>().err(),
+            Some(QueryError::NoMatch)
+        );
+
+        // Only the changed row counts, and it's the one handed out
+        *world.get_component_mut::<u32>(thing).unwrap() = 3;
+        let (value, changed) = world.try_query_one::<(&u32, Changed<u32>)>().unwrap();
+        assert_eq!((*value, changed), (3, true));
     }
 }
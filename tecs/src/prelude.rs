@@ -1,2 +1,8 @@
-pub use crate::{vecany::VecAny, System, SystemMut, EntityId, Is, With, Without};
+pub use crate::{
+    resource_equals, resource_exists,
+    system::{Res, ResMut, Single},
+    vecany::VecAny,
+    Added, AnyOf, Changed, Children, EntityId, EventReader, EventWriter, Events, Is, Join, Or,
+    Parent, Sparse, Stage, System, SystemMut, With, Without,
+};
 pub use tecs_derive::*;
@@ -0,0 +1,274 @@
+//! Component names, type names and serialized views of entities for tools
+//! that don't know the Rust types, filled in by the `Archetype` derive.
+
+use std::{
+    any::{type_name, TypeId},
+    marker::PhantomData,
+};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::{EntityId, RowIndex, Table, World};
+
+type SerializeComponentFn = fn(&Table, RowIndex) -> Option<Box<dyn erased_serde::Serialize>>;
+
+/// A component of an archetype
+#[derive(Clone, Copy, Debug)]
+pub struct Component {
+    /// The field the component is stored in, or the type name without its
+    /// path for components inserted outside of the archetype
+    pub name: &'static str,
+    pub type_name: &'static str,
+    pub ty: TypeId,
+    serialize: Option<SerializeComponentFn>,
+}
+
+impl Component {
+    /// A component that can't be serialized
+    pub fn new<T: 'static>(name: &'static str) -> Self {
+        Self {
+            name,
+            type_name: type_name::<T>(),
+            ty: TypeId::of::<T>(),
+            serialize: None,
+        }
+    }
+
+    pub fn serialized<T: Serialize + Clone + 'static>(name: &'static str) -> Self {
+        Self {
+            serialize: Some(|table, row| {
+                let value = table.column::<T>()?.get(row.0 as usize)?.clone();
+                Some(Box::new(value) as Box<dyn erased_serde::Serialize>)
+            }),
+            ..Self::new::<T>(name)
+        }
+    }
+
+    /// Component inserted with [`World::insert_component`], named after its type
+    pub(crate) fn inserted<T: 'static>() -> Self {
+        let name = type_name::<T>();
+        Self::new::<T>(name.rsplit("::").next().unwrap_or(name))
+    }
+
+    pub fn is_serializable(&self) -> bool {
+        self.serialize.is_some()
+    }
+
+    /// A serializable view of the component in `row`, `None` if it can't be
+    /// serialized or the table doesn't have it
+    pub fn serialize(
+        &self,
+        table: &Table,
+        row: RowIndex,
+    ) -> Option<Box<dyn erased_serde::Serialize>> {
+        self.serialize?(table, row)
+    }
+}
+
+/// Picks [`Component::serialized`] for types that implement `Serialize` and
+/// [`Component::new`] for the rest, used by the derive as
+/// `(&&Reflect::<T>::new()).component(name)`. Generic fields never count as
+/// serializable as the choice is made before they are known.
+pub struct Reflect<T>(PhantomData<T>);
+
+impl<T> Reflect<T> {
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        Self(PhantomData)
+    }
+}
+
+pub trait ReflectSerialize {
+    fn component(&self, name: &'static str) -> Component;
+}
+
+impl<T: Serialize + Clone + 'static> ReflectSerialize for &Reflect<T> {
+    fn component(&self, name: &'static str) -> Component {
+        Component::serialized::<T>(name)
+    }
+}
+
+pub trait ReflectOpaque {
+    fn component(&self, name: &'static str) -> Component;
+}
+
+impl<T: 'static> ReflectOpaque for Reflect<T> {
+    fn component(&self, name: &'static str) -> Component {
+        Component::new::<T>(name)
+    }
+}
+
+/// A registered archetype and how many entities it has
+#[derive(Clone, Debug)]
+pub struct ArchetypeInfo {
+    pub name: &'static str,
+    pub version: u32,
+    pub components: Vec<Component>,
+    pub entities: usize,
+}
+
+impl<E> World<E> {
+    pub fn archetype_infos(&self) -> Vec<ArchetypeInfo> {
+        let mut infos = self
+            .archetypes
+            .iter()
+            .map(|(archetype, id)| {
+                let table = self.tables.get(*id);
+                ArchetypeInfo {
+                    name: table.name,
+                    version: table.version,
+                    components: table.components.clone(),
+                    entities: self
+                        .tables
+                        .iter()
+                        .filter(|other| other.archetype == *archetype)
+                        .map(Table::len)
+                        .sum(),
+                }
+            })
+            .collect::<Vec<_>>();
+        infos.sort_by_key(|info| info.name);
+        infos
+    }
+
+    /// Every entity alive with the name of its archetype
+    pub fn entity_list(&self) -> Vec<(EntityId, &'static str)> {
+        let mut entities = self
+            .tables
+            .iter()
+            .flat_map(|table| {
+                table
+                    .entities
+                    .borrow()
+                    .iter()
+                    .map(|id| (*id, table.name))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        entities.sort_by_key(|(id, _)| id.index());
+        entities
+    }
+
+    /// The components `entity` has, including ones inserted on top of its
+    /// archetype
+    pub fn entity_components(&self, entity: EntityId) -> Option<Vec<Component>> {
+        let (table, _) = self.entities.lock().get(entity)?;
+        let table = self.tables.get(table);
+        let base = self.tables.get(self.archetypes[&table.archetype]);
+        let inserted = self.inserted.lock();
+        Some(
+            table
+                .column_types()
+                .chain(self.sparse.types(entity))
+                .filter_map(|ty| {
+                    base.components
+                        .iter()
+                        .find(|component| component.ty == ty)
+                        .or_else(|| inserted.get(&ty))
+                        .copied()
+                })
+                .collect(),
+        )
+    }
+
+    /// The archetype and components of `entity` as JSON, components that
+    /// can't be serialized only have their type
+    ///
+    /// ```json
+    /// { "archetype": "Player", "components": { "health": { "type": "thanatos::player::Health" } } }
+    /// ```
+    pub fn dump_entity(&self, entity: EntityId) -> Option<Value> {
+        let components = self.entity_components(entity)?;
+        let (table, row) = self.entities.lock().get(entity)?;
+        let table = self.tables.get(table);
+
+        let components = components
+            .into_iter()
+            .map(|component| {
+                let mut fields = Map::new();
+                fields.insert(String::from("type"), Value::from(component.type_name));
+                if let Some(value) = component
+                    .serialize(table, row)
+                    .and_then(|value| serde_json::to_value(value).ok())
+                {
+                    fields.insert(String::from("value"), value);
+                }
+                (component.name.to_string(), Value::Object(fields))
+            })
+            .collect::<Map<_, _>>();
+
+        let mut dump = Map::new();
+        dump.insert(String::from("archetype"), Value::from(table.name));
+        dump.insert(String::from("components"), Value::Object(components));
+        Some(Value::Object(dump))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use serde_json::json;
+
+    #[derive(Clone, Debug, Serialize)]
+    struct Position(f32, f32);
+
+    #[derive(Clone, Debug)]
+    struct Secret;
+
+    #[derive(Bundle, Clone)]
+    struct Body {
+        position: Position,
+    }
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        #[archetype(flatten)]
+        body: Body,
+        secret: Secret,
+        #[archetype(skip)]
+        _skipped: u8,
+    }
+
+    #[test]
+    pub fn test_dump_entity() {
+        let world = World::<()>::new().register_unsaved::<Thing>();
+        let thing = world.spawn(Thing {
+            body: Body {
+                position: Position(1.0, 2.0),
+            },
+            secret: Secret,
+            _skipped: 0,
+        });
+        world.insert_component(thing, 4_u64).unwrap();
+
+        assert_eq!(
+            world.dump_entity(thing),
+            Some(json!({
+                "archetype": "Thing",
+                "components": {
+                    "position": { "type": type_name::<Position>(), "value": [1.0, 2.0] },
+                    "secret": { "type": type_name::<Secret>() },
+                    "u64": { "type": "u64" },
+                }
+            }))
+        );
+
+        let infos = world.archetype_infos();
+        assert_eq!(infos.len(), 1);
+        assert_eq!(infos[0].entities, 1);
+        assert_eq!(
+            infos[0]
+                .components
+                .iter()
+                .map(|component| (component.name, component.is_serializable()))
+                .collect::<Vec<_>>(),
+            vec![("secret", false), ("position", true)]
+        );
+        assert_eq!(world.entity_list(), vec![(thing, "Thing")]);
+
+        world.despawn(thing).unwrap();
+        assert_eq!(world.dump_entity(thing), None);
+    }
+}
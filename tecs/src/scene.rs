@@ -1,13 +1,138 @@
 use std::{
     any::TypeId,
-    collections::HashMap,
-    hash::{DefaultHasher, Hash, Hasher},
+    collections::{BTreeMap, BTreeSet, HashMap},
 };
 
 use erased_serde::Serialize;
-use serde::{de::{DeserializeSeed, Visitor}, Deserializer};
+use serde::{
+    de::{DeserializeSeed, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize as _,
+};
+
+mod binary;
+mod prefab;
+
+pub use prefab::{Prefab, PrefabInstance};
+
+use crate::{
+    entity::Entities, sync::Mutex, Archetype, DeserializeArchetype, EntityId, Table, TableId, World,
+};
+
+/// Upgrades a saved row of an archetype by one schema version
+pub type Migration = fn(&mut serde_json::Value);
+
+/// Version of the layout of the scene itself, scenes without a header are
+/// from before it was versioned and count as version 0
+pub const FORMAT_VERSION: u32 = 1;
 
-use crate::{DeserializeArchetype, EntityId, RowIndex, Table, World};
+/// Reserved keys alongside the archetype names
+const HEADER: &str = "header";
+const PREFABS: &str = "prefabs";
+const PARENTS: &str = "parents";
+
+#[derive(serde::Serialize, Deserialize, Default)]
+struct Header {
+    version: u32,
+    /// Schema version of each archetype in the scene
+    schemas: BTreeMap<String, u32>,
+}
+
+/// The header, rows grouped by the name of their archetype, the overrides of
+/// prefab instances grouped by prefab, then `(child, parent)` pairs of
+/// indices into the rows in the order they were written
+struct SavedScene {
+    header: Header,
+    archetypes: Vec<(&'static str, Vec<Box<dyn Serialize>>)>,
+    prefabs: BTreeMap<String, Vec<serde_json::Value>>,
+    parents: Vec<(u32, u32)>,
+}
+
+impl serde::Serialize for SavedScene {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let prefabs = !self.prefabs.is_empty();
+        let mut map =
+            serializer.serialize_map(Some(self.archetypes.len() + 2 + prefabs as usize))?;
+        map.serialize_entry(HEADER, &self.header)?;
+        for (name, rows) in &self.archetypes {
+            map.serialize_entry(name, rows)?;
+        }
+        if prefabs {
+            map.serialize_entry(PREFABS, &self.prefabs)?;
+        }
+        map.serialize_entry(PARENTS, &self.parents)?;
+        map.end()
+    }
+}
+
+enum SceneKey {
+    Header,
+    Archetype(String),
+    Prefabs,
+    Parents,
+}
+
+impl<'de> Deserialize<'de> for SceneKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct KeyVisitor;
+
+        impl Visitor<'_> for KeyVisitor {
+            type Value = SceneKey;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(
+                    f,
+                    "An archetype name, \"{HEADER}\", \"{PREFABS}\" or \"{PARENTS}\""
+                )
+            }
+
+            fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(match v {
+                    HEADER => SceneKey::Header,
+                    PREFABS => SceneKey::Prefabs,
+                    PARENTS => SceneKey::Parents,
+                    _ => SceneKey::Archetype(v.to_string()),
+                })
+            }
+        }
+
+        deserializer.deserialize_str(KeyVisitor)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SceneFormat {
+    /// Human readable, for scenes edited by hand or kept in version control
+    Json,
+    /// Chunked MessagePack with a table of contents, for large scenes that
+    /// need to load fast
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Json(serde_json::Error),
+    Binary(String),
+    Prefab(String),
+}
+
+impl std::fmt::Display for SceneError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Json(e) => write!(f, "Invalid JSON scene: {e}"),
+            Self::Binary(e) => write!(f, "Invalid binary scene: {e}"),
+            Self::Prefab(e) => write!(f, "Failed to spawn prefab: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Json(value)
+    }
+}
 
 #[derive(Clone, Default)]
 pub struct Scene {
@@ -22,10 +147,9 @@ impl Scene {
     pub fn from_world<E>(&mut self, world: &World<E>) {
         world
             .entities
-            .borrow()
-            .keys()
-            .copied()
-            .for_each(|id| self.add(id));
+            .lock()
+            .iter()
+            .for_each(|(id, _)| self.add(id));
     }
 
     pub fn save<E, S: serde::Serializer>(
@@ -34,65 +158,313 @@ impl Scene {
         serializer: S,
     ) -> Result<(), erased_serde::Error> {
         let mut serializer = <dyn erased_serde::Serializer>::erase(serializer);
-        let entities = world.entities.borrow();
-        let mut entity_map: HashMap<TypeId, Vec<RowIndex>> = HashMap::new();
+        (Box::new(self.collect(world)) as Box<dyn erased_serde::Serialize>)
+            .erased_serialize(&mut serializer)?;
+
+        Ok(())
+    }
+
+    /// Saves the scene to bytes in either format, both can be read back with
+    /// [`Scene::load_bytes`]
+    pub fn save_bytes<E>(
+        &self,
+        world: &World<E>,
+        format: SceneFormat,
+    ) -> Result<Vec<u8>, SceneError> {
+        match format {
+            SceneFormat::Json => {
+                let mut buffer = Vec::new();
+                let mut serializer = serde_json::Serializer::pretty(&mut buffer);
+                self.collect(world).serialize(&mut serializer)?;
+                Ok(buffer)
+            }
+            SceneFormat::Binary => binary::write(&self.collect(world)),
+        }
+    }
 
-        self.entities
+    fn collect<E>(&self, world: &World<E>) -> SavedScene {
+        let entities = world.entities.lock();
+        let locations = self
+            .entities
             .iter()
-            .filter_map(|id| entities.get(&id).cloned())
-            .for_each(|(table, row)| match entity_map.get_mut(&table) {
-                Some(rows) => rows.push(row),
-                None => {
-                    entity_map.insert(table, vec![row]);
-                }
-            });
-
-        let scene: HashMap<u64, Vec<Box<dyn Serialize>>> = entity_map
-            .into_iter()
-            .map(|(id, rows)| {
-                let table = world.archetypes.get(&id).unwrap();
-                let rows = rows
-                    .into_iter()
-                    .map(|row| (table.serialize.unwrap())(table, row))
-                    .collect::<Vec<_>>();
-
-                let mut hasher = DefaultHasher::new();
-                id.hash(&mut hasher);
-                (hasher.finish(), rows)
-            })
-            .collect();
+            .filter_map(|id| Some((*id, entities.get(*id)?)))
+            .collect::<Vec<_>>();
+        drop(entities);
 
-        (Box::new(scene) as Box<dyn erased_serde::Serialize>).erased_serialize(&mut serializer)?;
+        let mut entity_map = BTreeMap::<&'static str, Vec<_>>::new();
+        let mut prefab_map = BTreeMap::<String, Vec<_>>::new();
+        let mut schemas = BTreeMap::new();
+        for (id, (table, row)) in locations {
+            let table = world.tables.get(table);
+            let Some(row) = table.serialize.map(|serialize| serialize(table, row)) else {
+                continue;
+            };
 
-        Ok(())
+            // Overrides of prefab instances are written at the schema of
+            // their archetype too
+            schemas.insert(table.name.to_string(), table.version);
+            if let Some((prefab, overrides)) = world.overrides(id, &row) {
+                prefab_map.entry(prefab).or_default().push((id, overrides));
+                continue;
+            }
+
+            entity_map.entry(table.name).or_default().push((id, row))
+        }
+
+        // Entities are referred to by their position in the file
+        let indices = entity_map
+            .values()
+            .flat_map(|rows| rows.iter().map(|(id, _)| *id))
+            .chain(
+                prefab_map
+                    .values()
+                    .flat_map(|rows| rows.iter().map(|(id, _)| *id)),
+            )
+            .enumerate()
+            .map(|(index, id)| (id, index as u32))
+            .collect::<HashMap<_, _>>();
+        let parents = indices
+            .iter()
+            .filter_map(|(id, index)| Some((*index, *indices.get(&world.parent(*id)?)?)))
+            .collect::<BTreeSet<_>>();
+
+        SavedScene {
+            header: Header {
+                version: FORMAT_VERSION,
+                schemas,
+            },
+            archetypes: entity_map
+                .into_iter()
+                .map(|(name, rows)| (name, rows.into_iter().map(|(_, row)| row).collect()))
+                .collect(),
+            prefabs: prefab_map
+                .into_iter()
+                .map(|(name, rows)| (name, rows.into_iter().map(|(_, row)| row).collect()))
+                .collect(),
+            parents: parents.into_iter().collect(),
+        }
     }
 
+    pub fn entities(&self) -> &[EntityId] {
+        &self.entities
+    }
+
+    /// Despawns the entities in the scene, leaving anything else in the
+    /// world, including entities from other scenes, untouched
+    pub fn unload<E>(&self, world: &World<E>) {
+        self.entities.iter().for_each(|id| {
+            let _ = world.despawn(*id);
+        })
+    }
+
+    /// Spawns the entities in a saved scene alongside whatever is already in
+    /// the world, returning them as a scene so they can be unloaded again
     pub fn load<'a, E, D: serde::Deserializer<'a>>(
         world: &'a World<E>,
         deserializer: D,
-    ) -> Result<Self, <D as Deserializer>::Error> {
+    ) -> Result<Self, <D as Deserializer<'a>>::Error> {
         deserializer.deserialize_map(ArchetypesSeed { world })
     }
+
+    /// Loads a scene saved by [`Scene::save_bytes`], telling the formats
+    /// apart by the magic at the start of binary scenes
+    pub fn load_bytes<E>(world: &World<E>, bytes: &[u8]) -> Result<Self, SceneError> {
+        if bytes.starts_with(binary::MAGIC) {
+            binary::read(world, bytes)
+        } else {
+            Ok(Self::load(
+                world,
+                &mut serde_json::Deserializer::from_slice(bytes),
+            )?)
+        }
+    }
 }
 
 #[derive(Clone, Copy)]
 struct EntitiesSeed<'a> {
+    ty: TableId,
     table: &'a Table,
+    entities: &'a Mutex<Entities>,
+    tick: u32,
+}
+
+impl EntitiesSeed<'_> {
+    /// Spawns a single row that has been through migrations
+    fn row(self, row: serde_json::Value) -> Result<EntityId, serde_json::Error> {
+        DeserializeArchetype {
+            ty: self.ty,
+            table: self.table,
+            entities: self.entities,
+            tick: self.tick,
+            func: self.table.deserialize.unwrap(),
+        }
+        .deserialize(row)
+    }
+}
+
+impl<E> World<E> {
+    /// Registers a migration upgrading saved rows of `T` from schema version
+    /// `from` to `from + 1`, run when loading scenes saved with older versions.
+    pub fn with_migration<T: Archetype>(mut self, from: u32, migration: Migration) -> Self {
+        self.migrations.insert((TypeId::of::<T>(), from), migration);
+        self
+    }
+
+    /// The migrations to run in order on rows saved at `version`
+    fn migrations(&self, table: &Table, version: u32) -> Result<Vec<Migration>, String> {
+        self.migrations_between(table, version, table.version)
+    }
+
+    /// The migrations to run in order to bring rows from schema version
+    /// `from` up to `to`
+    fn migrations_between(
+        &self,
+        table: &Table,
+        from: u32,
+        to: u32,
+    ) -> Result<Vec<Migration>, String> {
+        let newest = from.max(to);
+        if newest > table.version {
+            return Err(format!(
+                "{} was saved with schema version {newest}, newer than {}",
+                table.name, table.version
+            ));
+        }
+
+        (from..to)
+            .map(|version| {
+                self.migrations
+                    .get(&(table.archetype, version))
+                    .copied()
+                    .ok_or_else(|| {
+                        format!(
+                            "Missing migration for {} from schema version {version}",
+                            table.name
+                        )
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Spawns the rows of a scene one archetype at a time, shared by every format.
+/// Dropping the loader before [`Loader::finish`] succeeds despawns everything
+/// it spawned, so a scene that fails to load leaves nothing behind.
+struct Loader<'a, E> {
+    world: &'a World<E>,
+    entities: Vec<EntityId>,
+    /// Schema version each archetype was saved at
+    schemas: BTreeMap<String, u32>,
+}
+
+impl<'a, E> Loader<'a, E> {
+    fn new(world: &'a World<E>) -> Self {
+        Self {
+            world,
+            entities: Vec::new(),
+            schemas: BTreeMap::new(),
+        }
+    }
+
+    /// Seed for the rows of the archetype called `name` saved at schema
+    /// `version`, migrating them if it is out of date
+    fn rows(&mut self, name: &str, version: u32) -> Result<RowsSeed<'_, 'a>, String> {
+        let id = self
+            .world
+            .archetype_table(name)
+            .ok_or_else(|| format!("Unknown archetype {name}, it may need registering"))?;
+        self.schemas.insert(name.to_string(), version);
+        let table = self.world.tables.get(id);
+        Ok(RowsSeed {
+            seed: EntitiesSeed {
+                ty: id,
+                table,
+                entities: &self.world.entities,
+                tick: self.world.change_tick.get(),
+            },
+            migrations: self.world.migrations(table, version)?,
+            entities: &mut self.entities,
+        })
+    }
+
+    /// Spawns instances of prefabs from their overrides, which were written
+    /// at the schema their archetype was saved at. Scenes from before that
+    /// was recorded wrote them at the current schema.
+    fn prefabs(&mut self, prefabs: BTreeMap<String, Vec<serde_json::Value>>) -> Result<(), String> {
+        for (name, instances) in prefabs {
+            let version = self
+                .world
+                .prefabs
+                .get(&name)
+                .and_then(|prefab| self.schemas.get(&prefab.archetype))
+                .copied();
+            for overrides in instances {
+                let id = self.world.instantiate(&name, overrides, version)?;
+                self.entities.push(id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Links up the loaded entities using `(child, parent)` indices
+    fn finish(mut self, parents: Vec<(u32, u32)>) -> Result<Scene, String> {
+        for (child, parent) in parents {
+            let (Some(child), Some(parent)) = (
+                self.entities.get(child as usize),
+                self.entities.get(parent as usize),
+            ) else {
+                return Err(String::from("Parent link out of bounds"));
+            };
+            self.world
+                .set_parent(*child, *parent)
+                .map_err(|e| e.to_string())?;
+        }
+
+        Ok(Scene {
+            entities: std::mem::take(&mut self.entities),
+        })
+    }
 }
 
-impl<'de> DeserializeSeed<'de> for EntitiesSeed<'de> {
-    type Value = Vec<RowIndex>;
+impl<E> Drop for Loader<'_, E> {
+    fn drop(&mut self) {
+        self.entities.iter().for_each(|id| {
+            let _ = self.world.despawn(*id);
+        })
+    }
+}
+
+struct RowsSeed<'l, 'a> {
+    seed: EntitiesSeed<'a>,
+    migrations: Vec<Migration>,
+    entities: &'l mut Vec<EntityId>,
+}
+
+impl<'de> DeserializeSeed<'de> for RowsSeed<'_, 'de> {
+    type Value = ();
 
     fn deserialize<D>(self, deserializer: D) -> Result<Self::Value, D::Error>
     where
         D: serde::Deserializer<'de>,
     {
-        deserializer.deserialize_seq(self)
+        if self.migrations.is_empty() {
+            return deserializer.deserialize_seq(self);
+        }
+
+        let rows = Vec::<serde_json::Value>::deserialize(deserializer)?;
+        for mut row in rows {
+            self.migrations
+                .iter()
+                .for_each(|migration| migration(&mut row));
+            let id = self.seed.row(row).map_err(serde::de::Error::custom)?;
+            self.entities.push(id);
+        }
+        Ok(())
     }
 }
 
-impl<'de> Visitor<'de> for EntitiesSeed<'de> {
-    type Value = Vec<RowIndex>;
+impl<'de> Visitor<'de> for RowsSeed<'_, 'de> {
+    type Value = ();
 
     fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "Expect entities")
@@ -102,15 +474,18 @@ impl<'de> Visitor<'de> for EntitiesSeed<'de> {
     where
         A: serde::de::SeqAccess<'de>,
     {
-        let mut rows = Vec::new();
         let seed = DeserializeArchetype {
-            table: self.table,
-            func: self.table.deserialize.unwrap(),
+            ty: self.seed.ty,
+            table: self.seed.table,
+            entities: self.seed.entities,
+            tick: self.seed.tick,
+            func: self.seed.table.deserialize.unwrap(),
         };
-        while let Some(row) = seq.next_element_seed(seed)? {
-            rows.push(row);
+        // Kept as they spawn so the loader can despawn them if a later row fails
+        while let Some(id) = seq.next_element_seed(seed)? {
+            self.entities.push(id);
         }
-        Ok(rows)
+        Ok(())
     }
 }
 
@@ -125,36 +500,398 @@ impl<'de, E> Visitor<'de> for ArchetypesSeed<'de, E> {
     where
         A: serde::de::MapAccess<'de>,
     {
-        let mut entities: Vec<EntityId> = Vec::new();
-        while let Some(hash) = map.next_key()? {
-            let (id, table) = self
-                .world
-                .archetypes
+        let mut loader = Loader::new(self.world);
+        let mut parents: Vec<(u32, u32)> = Vec::new();
+        let mut header = None;
+        while let Some(key) = map.next_key::<SceneKey>()? {
+            let name = match key {
+                SceneKey::Archetype(name) => name,
+                SceneKey::Header if header.is_none() && loader.entities.is_empty() => {
+                    let read: Header = map.next_value()?;
+                    if read.version > FORMAT_VERSION {
+                        return Err(serde::de::Error::custom(format!(
+                            "Scene format version {} is newer than {FORMAT_VERSION}",
+                            read.version
+                        )));
+                    }
+                    loader.schemas = read.schemas.clone();
+                    header = Some(read);
+                    continue;
+                }
+                SceneKey::Header => {
+                    return Err(serde::de::Error::custom("Scene header must come first"))
+                }
+                SceneKey::Prefabs => {
+                    loader
+                        .prefabs(map.next_value()?)
+                        .map_err(serde::de::Error::custom)?;
+                    continue;
+                }
+                SceneKey::Parents => {
+                    parents = map.next_value()?;
+                    continue;
+                }
+            };
+
+            let version = header
+                .as_ref()
+                .and_then(|header| header.schemas.get(&name).copied())
+                .unwrap_or(0);
+            let seed = loader
+                .rows(&name, version)
+                .map_err(serde::de::Error::custom)?;
+            map.next_value_seed(seed)?;
+        }
+
+        loader.finish(parents).map_err(serde::de::Error::custom)
+    }
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Map of archetype names to entities")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::{Deserialize, Serialize};
+    use tecs_derive::Archetype;
+
+    #[derive(Archetype, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Thing {
+        value: u32,
+    }
+
+    #[derive(Archetype, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[archetype(name = "other")]
+    struct Other {
+        name: String,
+    }
+
+    #[test]
+    pub fn test_save_migrated() {
+        let world = World::<()>::new().register::<Thing>();
+        let a = world.spawn(Thing { value: 0 });
+        let b = world.spawn(Thing { value: 1 });
+        world.insert_component(a, 0.5_f32).unwrap();
+
+        let mut scene = Scene::default();
+        scene.from_world(&world);
+        let mut buffer = Vec::new();
+        scene
+            .save(&world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+
+        let loaded = World::<()>::new().register::<Thing>();
+        Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&buffer)).unwrap();
+        let values = loaded.query::<&u32>();
+        let mut values = values.iter().copied().collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec![0, 1]);
+
+        world.remove_component::<u32>(b).unwrap();
+        let mut buffer = Vec::new();
+        scene
+            .save(&world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+        let loaded = World::<()>::new().register::<Thing>();
+        Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&buffer)).unwrap();
+        assert_eq!(loaded.query::<&u32>().iter().collect::<Vec<_>>(), vec![&0]);
+    }
+
+    #[test]
+    pub fn test_save_parents() {
+        let world = World::<()>::new().register::<Thing>();
+        let ids = (0..4)
+            .map(|value| world.spawn(Thing { value }))
+            .collect::<Vec<_>>();
+        world.set_parent(ids[1], ids[0]).unwrap();
+        world.set_parent(ids[2], ids[1]).unwrap();
+        world.set_parent(ids[3], ids[0]).unwrap();
+
+        let mut scene = Scene::default();
+        scene.from_world(&world);
+        let mut buffer = Vec::new();
+        scene
+            .save(&world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+
+        let loaded = World::<()>::new().register::<Thing>();
+        Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&buffer)).unwrap();
+        let value = |id| *loaded.get_component::<u32>(id).unwrap();
+        let (entities, values) = loaded.query::<(EntityId, &u32)>();
+        let find = |value| {
+            *entities
                 .iter()
-                .find(|(id, _)| {
-                    let mut hasher = DefaultHasher::new();
-                    id.hash(&mut hasher);
-                    let id_hash = hasher.finish();
-                    id_hash == hash
-                })
-                .expect("Missing archetype needed to deserialize scene");
-
-            let seed = EntitiesSeed { table };
-            let mut world = self.world.entities.borrow_mut();
-            let rows = map.next_value_seed(seed)?;
-            rows.into_iter().for_each(|row| {
-                world.insert(EntityId(self.world.next_id.get()), (*id, row));
-                entities.push(EntityId(self.world.next_id.get()));
-                self.world.next_id.set(self.world.next_id.get() + 1)
+                .zip(values.iter())
+                .find(|(_, other)| **other == value)
+                .unwrap()
+                .0
+        };
+
+        assert_eq!(loaded.parent(find(0)), None);
+        assert_eq!(
+            loaded
+                .children(find(0))
+                .into_iter()
+                .map(value)
+                .collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert_eq!(loaded.parent(find(2)).map(value), Some(1));
+    }
+
+    #[test]
+    pub fn test_names() {
+        let world = World::<()>::new().register::<Thing>().register::<Other>();
+        world.spawn(Thing { value: 0 });
+        world.spawn(Other {
+            name: String::from("a"),
+        });
+
+        let mut scene = Scene::default();
+        scene.from_world(&world);
+        let mut buffer = Vec::new();
+        scene
+            .save(&world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(buffer.clone()).unwrap(),
+            r#"{"header":{"version":1,"schemas":{"Thing":0,"other":0}},"Thing":[{"value":0}],"other":[{"name":"a"}],"parents":[]}"#
+        );
+
+        let loaded = World::<()>::new().register::<Thing>();
+        let error = Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&buffer))
+            .err()
+            .unwrap();
+        assert!(error.to_string().starts_with("Unknown archetype other"));
+    }
+
+    #[test]
+    pub fn test_formats() {
+        let world = World::<()>::new().register::<Thing>().register::<Other>();
+        let ids = (0..100)
+            .map(|value| world.spawn(Thing { value }))
+            .collect::<Vec<_>>();
+        let other = world.spawn(Other {
+            name: String::from("a"),
+        });
+        world.set_parent(ids[1], ids[0]).unwrap();
+        world.set_parent(other, ids[1]).unwrap();
+
+        let mut scene = Scene::default();
+        scene.from_world(&world);
+        let json = scene.save_bytes(&world, SceneFormat::Json).unwrap();
+        let binary = scene.save_bytes(&world, SceneFormat::Binary).unwrap();
+        assert!(binary.starts_with(binary::MAGIC));
+        assert!(binary.len() < json.len());
+
+        let resaved = [json, binary].map(|buffer| {
+            let loaded = World::<()>::new().register::<Thing>().register::<Other>();
+            let scene = Scene::load_bytes(&loaded, &buffer).unwrap();
+            assert_eq!(scene.entities().len(), 101);
+            scene.save_bytes(&loaded, SceneFormat::Json).unwrap()
+        });
+        assert_eq!(resaved[0], resaved[1]);
+        assert_eq!(
+            resaved[0],
+            scene.save_bytes(&world, SceneFormat::Json).unwrap()
+        );
+
+        let loaded = World::<()>::new().register::<Thing>();
+        let binary = scene.save_bytes(&world, SceneFormat::Binary).unwrap();
+        assert!(Scene::load_bytes(&loaded, &binary[..binary.len() - 1]).is_err());
+        assert!(Scene::load_bytes(&loaded, &binary).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "Archetype name Thing is already registered")]
+    pub fn test_duplicate_name() {
+        #[derive(Archetype, Clone)]
+        #[archetype(name = "Thing")]
+        struct Renamed {
+            value: f32,
+        }
+
+        World::<()>::new()
+            .register::<Thing>()
+            .register_unsaved::<Renamed>();
+    }
+
+    fn save(world: &World<()>, scene: &Scene) -> Vec<u8> {
+        let mut buffer = Vec::new();
+        scene
+            .save(world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+        buffer
+    }
+
+    fn values(world: &World<()>) -> Vec<u32> {
+        let mut values = world.query::<&u32>().iter().copied().collect::<Vec<_>>();
+        values.sort();
+        values
+    }
+
+    #[test]
+    pub fn test_unload() {
+        let world = World::<()>::new().register::<Thing>();
+        let mut a = Scene::default();
+        a.add(world.spawn(Thing { value: 0 }));
+        a.add(world.spawn(Thing { value: 1 }));
+        let mut b = Scene::default();
+        b.add(world.spawn(Thing { value: 2 }));
+        let mut all = Scene::default();
+        all.from_world(&world);
+        let (a, b, all) = (save(&world, &a), save(&world, &b), save(&world, &all));
+
+        let loaded = World::<()>::new().register::<Thing>();
+        loaded.spawn(Thing { value: 10 });
+        let a = Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&a)).unwrap();
+        let b = Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&b)).unwrap();
+        assert_eq!(a.entities().len(), 2);
+        assert_eq!(values(&loaded), vec![0, 1, 2, 10]);
+        assert_eq!(
+            b.entities()
+                .iter()
+                .map(|id| *loaded.get_component::<u32>(*id).unwrap())
+                .collect::<Vec<_>>(),
+            vec![2]
+        );
+
+        a.unload(&loaded);
+        assert_eq!(values(&loaded), vec![2, 10]);
+        assert!(a.entities().iter().all(|id| !loaded.is_alive(*id)));
+
+        let all = Scene::load(&loaded, &mut serde_json::Deserializer::from_slice(&all)).unwrap();
+        b.unload(&loaded);
+        assert_eq!(values(&loaded), vec![0, 1, 2, 10]);
+        all.unload(&loaded);
+        assert_eq!(values(&loaded), vec![10]);
+    }
+
+    #[derive(Archetype, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    #[archetype(version = 2)]
+    struct Versioned {
+        amount: u32,
+        label: String,
+    }
+
+    fn versioned() -> World<()> {
+        World::<()>::new()
+            .register::<Versioned>()
+            .with_migration::<Versioned>(0, |row| {
+                let value = row["value"].take();
+                row["amount"] = value;
+            })
+            .with_migration::<Versioned>(1, |row| {
+                row["label"] = format!("#{}", row["amount"]).into();
             })
+    }
+
+    /// Every scene in `fixtures/scenes` was saved by an older version of
+    /// `Versioned` and should load to the same world
+    #[test]
+    pub fn test_fixtures() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/scenes");
+        let mut loaded = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let world = versioned();
+            let buffer = std::fs::read(&path).unwrap();
+            if let Err(e) = Scene::load(&world, &mut serde_json::Deserializer::from_slice(&buffer))
+            {
+                panic!("Failed to load {}: {e}", path.display());
+            }
+
+            let (amounts, labels) = world.query::<(&u32, &String)>();
+            let mut rows = amounts
+                .iter()
+                .copied()
+                .zip(labels.iter().cloned())
+                .collect::<Vec<_>>();
+            rows.sort();
+            assert_eq!(
+                rows,
+                vec![(1, String::from("#1")), (2, String::from("#2"))],
+                "{}",
+                path.display()
+            );
+            loaded += 1;
         }
+        assert_eq!(loaded, 3);
+    }
 
-        Ok(Scene {
-            entities: Vec::new(),
-        })
+    #[test]
+    pub fn test_missing_migration() {
+        let world = World::<()>::new().register::<Versioned>();
+        let buffer =
+            br#"{"header":{"version":1,"schemas":{"Versioned":1}},"Versioned":[{"amount":1}]}"#;
+        let error = Scene::load(&world, &mut serde_json::Deserializer::from_slice(buffer))
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Missing migration for Versioned from schema version 1"));
+
+        let buffer = br#"{"header":{"version":1,"schemas":{"Versioned":3}},"Versioned":[]}"#;
+        assert!(Scene::load(&world, &mut serde_json::Deserializer::from_slice(buffer)).is_err());
     }
 
-    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Map of archetype hashes to entities")
+    #[test]
+    pub fn test_prefab_migrated() {
+        let template = |version, template| Prefab {
+            archetype: String::from("Versioned"),
+            version,
+            template,
+        };
+        let buffer = br#"{"header":{"version":1,"schemas":{"Versioned":0}},"prefabs":{"crate":[{"value":7},{}]},"parents":[]}"#;
+        let world =
+            versioned().with_prefab("crate", template(0, serde_json::json!({ "value": 5 })));
+        Scene::load_bytes(&world, buffer).unwrap();
+        let (amounts, labels) = world.query::<(&u32, &String)>();
+        let mut rows = amounts
+            .iter()
+            .copied()
+            .zip(labels.iter().cloned())
+            .collect::<Vec<_>>();
+        rows.sort();
+        assert_eq!(rows, vec![(5, String::from("#5")), (7, String::from("#7"))]);
+
+        // The template is newer than the scene so the overrides are migrated
+        // on their own
+        let buffer = br#"{"header":{"version":1,"schemas":{"Versioned":1}},"prefabs":{"crate":[{"amount":7}]},"parents":[]}"#;
+        let world = versioned().with_prefab(
+            "crate",
+            template(2, serde_json::json!({ "amount": 5, "label": "crate" })),
+        );
+        let scene = Scene::load_bytes(&world, buffer).unwrap();
+        let id = scene.entities()[0];
+        assert_eq!(world.get_component::<u32>(id).as_deref(), Some(&7));
+        assert_eq!(
+            world.get_component::<String>(id).as_deref(),
+            Some(&String::from("#7"))
+        );
+    }
+
+    #[test]
+    pub fn test_load_failed() {
+        let world = World::<()>::new().register::<Thing>().register::<Other>();
+        world.spawn(Thing { value: 10 });
+        for buffer in [
+            &br#"{"Thing":[{"value":0},{"value":1}],"other":[{"name":3}]}"#[..],
+            br#"{"Thing":[{"value":0},{"value":"one"}]}"#,
+            br#"{"Thing":[{"value":0}],"parents":[[0,5]]}"#,
+        ] {
+            assert!(Scene::load_bytes(&world, buffer).is_err());
+            assert_eq!(values(&world), vec![10]);
+            assert_eq!(world.query::<&String>().iter().count(), 0);
+        }
+
+        let mut scene = Scene::default();
+        scene.from_world(&world);
+        let mut binary = scene.save_bytes(&world, SceneFormat::Binary).unwrap();
+        binary.truncate(binary.len() - 4);
+        assert!(Scene::load_bytes(&world, &binary).is_err());
+        assert_eq!(values(&world), vec![10]);
     }
 }
@@ -0,0 +1,151 @@
+//! Binary scenes, a table of contents followed by one MessagePack chunk per
+//! archetype so a chunk can be found without decoding the ones before it.
+//!
+//! ```text
+//! magic | format version: u32 | chunk count: u32
+//! per chunk: name length: u16 | name | schema version: u32 | offset: u64 | length: u64
+//! chunks
+//! ```
+//!
+//! Integers are little endian and offsets are from the start of the file.
+
+use serde::{de::DeserializeSeed, Serialize};
+
+use super::{Loader, SavedScene, Scene, SceneError, FORMAT_VERSION, PARENTS, PREFABS};
+use crate::World;
+
+pub const MAGIC: &[u8; 8] = b"TECSCENE";
+
+fn encode<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SceneError> {
+    let mut buffer = Vec::new();
+    value
+        .serialize(&mut rmp_serde::Serializer::new(&mut buffer).with_struct_map())
+        .map_err(|e| SceneError::Binary(e.to_string()))?;
+    Ok(buffer)
+}
+
+fn decode<'a, T: serde::Deserialize<'a>>(
+    deserializer: &mut rmp_serde::Deserializer<rmp_serde::decode::ReadRefReader<'a, [u8]>>,
+) -> Result<T, SceneError> {
+    T::deserialize(deserializer).map_err(|e| SceneError::Binary(e.to_string()))
+}
+
+pub(super) fn write(scene: &SavedScene) -> Result<Vec<u8>, SceneError> {
+    let mut chunks = scene
+        .archetypes
+        .iter()
+        .map(|(name, rows)| Ok((*name, scene.header.schemas[*name], encode(rows)?)))
+        .collect::<Result<Vec<_>, SceneError>>()?;
+    // Archetypes only used by prefab instances still need their schema
+    // version written, so they get a chunk with no rows
+    for (name, version) in &scene.header.schemas {
+        if !scene
+            .archetypes
+            .iter()
+            .any(|(archetype, _)| archetype == name)
+        {
+            chunks.push((name, *version, encode::<[()]>(&[])?));
+        }
+    }
+    if !scene.prefabs.is_empty() {
+        chunks.push((PREFABS, 0, encode(&scene.prefabs)?));
+    }
+    chunks.push((PARENTS, 0, encode(&scene.parents)?));
+
+    let toc = chunks
+        .iter()
+        .map(|(name, _, _)| 2 + name.len() + 4 + 8 + 8)
+        .sum::<usize>();
+    let mut offset = (MAGIC.len() + 4 + 4 + toc) as u64;
+
+    let mut buffer = Vec::from(*MAGIC);
+    buffer.extend(FORMAT_VERSION.to_le_bytes());
+    buffer.extend((chunks.len() as u32).to_le_bytes());
+    for (name, version, chunk) in &chunks {
+        let length = u16::try_from(name.len())
+            .map_err(|_| SceneError::Binary(format!("Archetype name {name} is too long")))?;
+        buffer.extend(length.to_le_bytes());
+        buffer.extend(name.as_bytes());
+        buffer.extend(version.to_le_bytes());
+        buffer.extend(offset.to_le_bytes());
+        buffer.extend((chunk.len() as u64).to_le_bytes());
+        offset += chunk.len() as u64;
+    }
+    chunks
+        .into_iter()
+        .for_each(|(_, _, chunk)| buffer.extend(chunk));
+
+    Ok(buffer)
+}
+
+/// Reads the table of contents a field at a time
+struct Cursor<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, length: usize) -> Result<&'a [u8], SceneError> {
+        let bytes = self
+            .position
+            .checked_add(length)
+            .and_then(|end| self.bytes.get(self.position..end))
+            .ok_or_else(|| SceneError::Binary(String::from("Unexpected end of file")))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn read<const N: usize>(&mut self) -> Result<[u8; N], SceneError> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+}
+
+pub(super) fn read<'a, E>(world: &'a World<E>, bytes: &'a [u8]) -> Result<Scene, SceneError> {
+    let mut cursor = Cursor {
+        bytes,
+        position: MAGIC.len(),
+    };
+    let version = u32::from_le_bytes(cursor.read()?);
+    if version > FORMAT_VERSION {
+        return Err(SceneError::Binary(format!(
+            "Scene format version {version} is newer than {FORMAT_VERSION}"
+        )));
+    }
+
+    let count = u32::from_le_bytes(cursor.read()?);
+    let mut loader = Loader::new(world);
+    let mut parents = Vec::new();
+    for _ in 0..count {
+        let length = u16::from_le_bytes(cursor.read()?);
+        let name = std::str::from_utf8(cursor.take(length as usize)?)
+            .map_err(|e| SceneError::Binary(e.to_string()))?;
+        let version = u32::from_le_bytes(cursor.read()?);
+        let offset = u64::from_le_bytes(cursor.read()?) as usize;
+        let length = u64::from_le_bytes(cursor.read()?) as usize;
+        let chunk = offset
+            .checked_add(length)
+            .and_then(|end| bytes.get(offset..end))
+            .ok_or_else(|| SceneError::Binary(format!("Chunk {name} out of bounds")))?;
+
+        let mut deserializer = rmp_serde::Deserializer::from_read_ref(chunk);
+        match name {
+            PREFABS => {
+                let prefabs = decode(&mut deserializer)?;
+                loader.prefabs(prefabs).map_err(SceneError::Prefab)?;
+                continue;
+            }
+            PARENTS => {
+                parents = decode(&mut deserializer)?;
+                continue;
+            }
+            _ => (),
+        }
+        loader
+            .rows(name, version)
+            .map_err(SceneError::Binary)?
+            .deserialize(&mut deserializer)
+            .map_err(|e| SceneError::Binary(format!("{name}: {e}")))?;
+    }
+
+    loader.finish(parents).map_err(SceneError::Binary)
+}
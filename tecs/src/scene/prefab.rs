@@ -0,0 +1,276 @@
+//! Prefabs are rows of an archetype kept outside of any scene, usually in
+//! their own file, so new instances can be spawned and saved with only the
+//! fields that differ from the template.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{EntitiesSeed, SceneError};
+use crate::{EntityId, TableId, World};
+
+/// A serialized template for an archetype, overrides given when spawning it
+/// are relative to the template at the archetype's current schema version
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Prefab {
+    pub archetype: String,
+    /// Schema version the template was written at
+    #[serde(default)]
+    pub version: u32,
+    pub template: Value,
+}
+
+impl Prefab {
+    pub fn load(bytes: &[u8]) -> Result<Self, SceneError> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// The prefab an entity was spawned from, saving a scene only stores the
+/// fields of the entity that differ from the prefab
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PrefabInstance(pub String);
+
+impl<E> World<E> {
+    pub fn with_prefab<T: ToString>(mut self, name: T, prefab: Prefab) -> Self {
+        self.prefabs.insert(name.to_string(), prefab);
+        self
+    }
+
+    /// Spawns an instance of the prefab called `name`, with the fields in
+    /// `overrides` replacing those in the template
+    pub fn spawn_prefab(&self, name: &str, overrides: Value) -> Result<EntityId, SceneError> {
+        self.instantiate(name, overrides, None)
+            .map_err(SceneError::Prefab)
+    }
+
+    /// Spawns an instance of the prefab called `name` from `overrides`
+    /// written at schema `version` of its archetype, or the current schema
+    /// when `None`
+    pub(super) fn instantiate(
+        &self,
+        name: &str,
+        overrides: Value,
+        version: Option<u32>,
+    ) -> Result<EntityId, String> {
+        let (id, row) = self.prefab_row(name, overrides, version)?;
+
+        let table = self.tables.get(id);
+        if table.deserialize.is_none() {
+            return Err(format!("{} can't be deserialized", table.name));
+        }
+        let entity = EntitiesSeed {
+            ty: id,
+            table,
+            entities: &self.entities,
+            tick: self.change_tick.get(),
+        }
+        .row(row)
+        .map_err(|e| format!("Prefab {name}: {e}"))?;
+        self.insert_component(entity, PrefabInstance(name.to_string()))
+            .map_err(|e| e.to_string())?;
+        Ok(entity)
+    }
+
+    /// The prefab `entity` was spawned from and the fields of its saved
+    /// `row` that differ from the prefab's template
+    pub(super) fn overrides(
+        &self,
+        entity: EntityId,
+        row: &dyn erased_serde::Serialize,
+    ) -> Option<(String, Value)> {
+        let prefab = self.get_component::<PrefabInstance>(entity)?.0.clone();
+        let (_, template) = self.prefab_template(&prefab).ok()?;
+        let overrides = diff(&template, serde_json::to_value(row).ok()?)
+            .unwrap_or_else(|| Value::Object(serde_json::Map::new()));
+        Some((prefab, overrides))
+    }
+
+    /// The template of the prefab called `name` migrated to the current
+    /// schema of its archetype
+    pub(super) fn prefab_template(&self, name: &str) -> Result<(TableId, Value), String> {
+        self.prefab_row(name, Value::Object(serde_json::Map::new()), None)
+    }
+
+    /// The row of an instance of the prefab called `name` at the current
+    /// schema of its archetype, from `overrides` written at schema `version`.
+    /// The row is rebuilt as it was saved and migrated as a whole, unless the
+    /// template is newer than the overrides in which case both are migrated
+    /// separately.
+    fn prefab_row(
+        &self,
+        name: &str,
+        mut overrides: Value,
+        version: Option<u32>,
+    ) -> Result<(TableId, Value), String> {
+        let prefab = self
+            .prefabs
+            .get(name)
+            .ok_or_else(|| format!("Unknown prefab {name}"))?;
+        let id = self.archetype_table(&prefab.archetype).ok_or_else(|| {
+            format!(
+                "Unknown archetype {}, it may need registering",
+                prefab.archetype
+            )
+        })?;
+        let table = self.tables.get(id);
+        let version = version.unwrap_or(table.version);
+        let migrate = |row: &mut Value, from, to| {
+            self.migrations_between(table, from, to)
+                .map(|migrations| migrations.into_iter().for_each(|migration| migration(row)))
+        };
+
+        let mut row = prefab.template.clone();
+        if prefab.version <= version {
+            migrate(&mut row, prefab.version, version)?;
+            merge(&mut row, overrides);
+            migrate(&mut row, version, table.version)?;
+        } else {
+            migrate(&mut row, prefab.version, table.version)?;
+            migrate(&mut overrides, version, table.version)?;
+            merge(&mut row, overrides);
+        }
+        Ok((id, row))
+    }
+}
+
+/// Replaces the fields of `row` with those in `overrides`, recursing into
+/// objects so only the fields given are replaced
+fn merge(row: &mut Value, overrides: Value) {
+    match (row, overrides) {
+        (Value::Object(row), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match row.get_mut(&key) {
+                    Some(field) => merge(field, value),
+                    None => {
+                        row.insert(key, value);
+                    }
+                }
+            }
+        }
+        (row, overrides) => *row = overrides,
+    }
+}
+
+/// The fields of `row` that differ from `template`, the inverse of [`merge`]
+fn diff(template: &Value, row: Value) -> Option<Value> {
+    match (template, row) {
+        (Value::Object(template), Value::Object(row)) => {
+            let fields = row
+                .into_iter()
+                .filter_map(|(key, value)| match template.get(&key) {
+                    Some(field) => Some((key, diff(field, value)?)),
+                    None => Some((key, value)),
+                })
+                .collect::<serde_json::Map<_, _>>();
+            (!fields.is_empty()).then_some(Value::Object(fields))
+        }
+        (template, row) => (!matches(template, &row)).then_some(row),
+    }
+}
+
+/// Whether `row` holds the value written in `template`, templates are written
+/// by hand so floats match the f32 they round to and whole numbers can be
+/// written without a decimal point
+fn matches(template: &Value, row: &Value) -> bool {
+    match (template, row) {
+        (Value::Number(template), Value::Number(row)) if template.is_f64() || row.is_f64() => {
+            template.as_f64().map(|x| x as f32 as f64) == row.as_f64()
+        }
+        (Value::Array(template), Value::Array(row)) => {
+            template.len() == row.len()
+                && template
+                    .iter()
+                    .zip(row)
+                    .all(|(template, row)| matches(template, row))
+        }
+        (Value::Object(template), Value::Object(row)) => {
+            template.len() == row.len()
+                && template
+                    .iter()
+                    .all(|(key, template)| row.get(key).is_some_and(|row| matches(template, row)))
+        }
+        (template, row) => template == row,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::*,
+        scene::{Scene, SceneFormat},
+    };
+    use serde_json::json;
+
+    #[derive(Archetype, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Ore {
+        position: (f32, f32),
+        radius: f32,
+        text: String,
+    }
+
+    fn ores() -> World<()> {
+        let prefab = br#"{
+            "archetype": "Ore",
+            "template": { "position": [0, 0], "radius": 0.1, "text": "Gather Ore" }
+        }"#;
+        World::<()>::new()
+            .register::<Ore>()
+            .with_prefab("ore", Prefab::load(prefab).unwrap())
+    }
+
+    #[test]
+    pub fn test_spawn_prefab() {
+        let world = ores();
+        let ore = world
+            .spawn_prefab("ore", json!({ "position": [1.0, 2.0] }))
+            .unwrap();
+        assert_eq!(
+            world.get_component::<(f32, f32)>(ore).as_deref(),
+            Some(&(1.0, 2.0))
+        );
+        assert_eq!(world.get_component::<f32>(ore).as_deref(), Some(&0.1));
+        assert_eq!(
+            world.get_component::<PrefabInstance>(ore).as_deref(),
+            Some(&PrefabInstance(String::from("ore")))
+        );
+
+        assert!(world.spawn_prefab("missing", json!({})).is_err());
+        assert!(world
+            .spawn_prefab("ore", json!({ "radius": "big" }))
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_save_overrides() {
+        let world = ores();
+        world
+            .spawn_prefab("ore", json!({ "position": [1.0, 2.0] }))
+            .unwrap();
+        world.spawn_prefab("ore", json!({})).unwrap();
+
+        let mut scene = Scene::default();
+        scene.from_world(&world);
+        let mut buffer = Vec::new();
+        scene
+            .save(&world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+        assert_eq!(
+            std::str::from_utf8(&buffer).unwrap(),
+            r#"{"header":{"version":1,"schemas":{"Ore":0}},"prefabs":{"ore":[{"position":[1.0,2.0]},{}]},"parents":[]}"#
+        );
+
+        let loaded = ores();
+        Scene::load_bytes(&loaded, &buffer).unwrap();
+        let positions = loaded.query::<&(f32, f32)>();
+        let mut positions = positions.iter().copied().collect::<Vec<_>>();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(positions, vec![(0.0, 0.0), (1.0, 2.0)]);
+        assert_eq!(loaded.query::<&PrefabInstance>().iter().count(), 2);
+
+        let binary = scene.save_bytes(&world, SceneFormat::Binary).unwrap();
+        let loaded = ores();
+        Scene::load_bytes(&loaded, &binary).unwrap();
+        assert_eq!(loaded.query::<&PrefabInstance>().iter().count(), 2);
+    }
+}
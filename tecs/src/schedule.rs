@@ -0,0 +1,591 @@
+use std::{any::TypeId, fmt::Display};
+
+use crate::{
+    sync::{Cell, MaybeSync, Shared},
+    Access, System, World, SYSTEM_LAST_RUN,
+};
+
+/// Systems run stage by stage in this order, and in the order they were
+/// added within a stage unless told otherwise with `before` and `after`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Stage {
+    PreUpdate,
+    #[default]
+    Update,
+    PostUpdate,
+    Render,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ScheduleError {
+    /// Names of the systems in the cycle, starting and ending with the same one
+    Cycle(Vec<&'static str>),
+    /// A system is ordered before or after a label no system has
+    UnknownLabel {
+        system: &'static str,
+        label: &'static str,
+    },
+    /// A system is ordered before one in an earlier stage, which can't happen
+    /// since stages run in order
+    StageOrder {
+        before: (&'static str, Stage),
+        after: (&'static str, Stage),
+    },
+    /// A function system needs a resource the world doesn't have
+    MissingResource {
+        system: &'static str,
+        resource: &'static str,
+    },
+    /// A function system borrows these types mutably more than once
+    Aliased {
+        system: &'static str,
+        types: Vec<&'static str>,
+    },
+    /// Two function systems in the same stage borrow these types in ways
+    /// that conflict without being ordered relative to each other
+    Conflict {
+        systems: (&'static str, &'static str),
+        types: Vec<&'static str>,
+    },
+}
+
+impl Display for ScheduleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Cycle(systems) => write!(f, "System ordering cycle: {}", systems.join(" -> ")),
+            Self::UnknownLabel { system, label } => {
+                write!(f, "System {system} is ordered relative to unknown label {label}")
+            }
+            Self::StageOrder {
+                before: (before, before_stage),
+                after: (after, after_stage),
+            } => write!(
+                f,
+                "System {before} in {before_stage:?} is ordered before {after} in the earlier stage {after_stage:?}"
+            ),
+            Self::MissingResource { system, resource } => write!(
+                f,
+                "System {system} needs resource {resource}, it may need adding with `with_resource`"
+            ),
+            Self::Aliased { system, types } => write!(
+                f,
+                "System {system} has aliased mutable access to {}",
+                types.join(", ")
+            ),
+            Self::Conflict {
+                systems: (a, b),
+                types,
+            } => write!(
+                f,
+                "Systems {a} and {b} have conflicting access to {}, order them with `before` or `after`",
+                types.join(", ")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+pub(crate) trait RunCondition<E>: Fn(&World<E>) -> bool + MaybeSync {}
+impl<E, T: Fn(&World<E>) -> bool + MaybeSync> RunCondition<E> for T {}
+
+pub(crate) type Condition<E> = Shared<dyn RunCondition<E>>;
+
+pub(crate) struct Scheduled<E> {
+    pub system: Shared<dyn System<E>>,
+    pub name: &'static str,
+    pub stage: Stage,
+    pub labels: Vec<&'static str>,
+    pub before: Vec<&'static str>,
+    pub after: Vec<&'static str>,
+    pub conditions: Vec<Condition<E>>,
+    /// What the system declared it accesses, `None` if it didn't
+    pub access: Option<Access>,
+    /// Resources the system can't run without
+    pub resources: Vec<(TypeId, &'static str)>,
+    /// Function systems have their access checked against each other
+    pub function: bool,
+    pub last_run: Cell<u32>,
+}
+
+impl<E> Scheduled<E> {
+    pub fn new(system: Shared<dyn System<E>>, name: &'static str) -> Self {
+        Self {
+            system,
+            name,
+            stage: Stage::default(),
+            labels: Vec::new(),
+            before: Vec::new(),
+            after: Vec::new(),
+            conditions: Vec::new(),
+            access: None,
+            resources: Vec::new(),
+            function: false,
+            last_run: Cell::new(0),
+        }
+    }
+
+    pub fn should_run(&self, world: &World<E>) -> bool {
+        self.conditions.iter().all(|condition| condition(world))
+    }
+
+    /// Calls `f` with this system's last run as the thread's change ticks
+    pub fn with_last_run<T>(&self, f: impl FnOnce() -> T) -> T {
+        let previous = SYSTEM_LAST_RUN.replace(Some(self.last_run.get()));
+        let result = f();
+        SYSTEM_LAST_RUN.set(previous);
+        result
+    }
+
+    pub fn run<F: Fn(&dyn System<E>)>(&self, this_run: u32, f: &F) {
+        self.with_last_run(|| f(&*self.system));
+        self.last_run.set(this_run);
+    }
+
+    /// Checks the system's resources exist and that its declared access
+    /// doesn't alias
+    pub fn validate<F: Fn(&TypeId) -> bool>(&self, exists: F) -> Result<(), ScheduleError> {
+        if let Some((_, resource)) = self.resources.iter().find(|(ty, _)| !exists(ty)) {
+            return Err(ScheduleError::MissingResource {
+                system: self.name(),
+                resource,
+            });
+        }
+
+        let types = self
+            .access
+            .as_ref()
+            .map(Access::aliased)
+            .unwrap_or_default();
+        if !types.is_empty() {
+            return Err(ScheduleError::Aliased {
+                system: self.name(),
+                types,
+            });
+        }
+        Ok(())
+    }
+
+    /// The first label, or the type name for unlabelled systems
+    fn name(&self) -> &'static str {
+        self.labels.first().copied().unwrap_or(self.name)
+    }
+
+    fn runs_before(&self, other: &Self) -> bool {
+        self.before.iter().any(|label| other.labels.contains(label))
+            || other.after.iter().any(|label| self.labels.contains(label))
+    }
+}
+
+/// Sorts the systems by stage, then topologically by their ordering
+/// constraints, falling back to insertion order.
+pub(crate) fn order<E>(systems: &[Scheduled<E>]) -> Result<Vec<usize>, ScheduleError> {
+    for system in systems {
+        if let Some(label) = system
+            .before
+            .iter()
+            .chain(&system.after)
+            .find(|label| !systems.iter().any(|other| other.labels.contains(label)))
+        {
+            return Err(ScheduleError::UnknownLabel {
+                system: system.name(),
+                label,
+            });
+        }
+    }
+
+    // Constraints across stages can only be broken, never enforced
+    for a in systems {
+        if let Some(b) = systems
+            .iter()
+            .find(|b| a.stage > b.stage && a.runs_before(b))
+        {
+            return Err(ScheduleError::StageOrder {
+                before: (a.name(), a.stage),
+                after: (b.name(), b.stage),
+            });
+        }
+    }
+
+    let mut stages = systems
+        .iter()
+        .map(|system| system.stage)
+        .collect::<Vec<_>>();
+    stages.sort();
+    stages.dedup();
+
+    let mut order = Vec::with_capacity(systems.len());
+    for stage in stages {
+        let mut remaining = (0..systems.len())
+            .filter(|i| systems[*i].stage == stage)
+            .collect::<Vec<_>>();
+
+        while !remaining.is_empty() {
+            let ready = remaining.iter().position(|i| {
+                !remaining
+                    .iter()
+                    .any(|j| systems[*j].runs_before(&systems[*i]))
+            });
+
+            match ready {
+                Some(ready) => order.push(remaining.remove(ready)),
+                None => return Err(ScheduleError::Cycle(cycle(systems, &remaining))),
+            }
+        }
+    }
+
+    Ok(order)
+}
+
+/// Checks function systems in the same stage that borrow the same types in
+/// conflicting ways are ordered relative to each other, directly or through
+/// other systems, so which runs first doesn't depend on when they were added.
+pub(crate) fn conflicts<E>(systems: &[Scheduled<E>]) -> Result<(), ScheduleError> {
+    for (i, a) in systems.iter().enumerate() {
+        for (j, b) in systems.iter().enumerate().skip(i + 1) {
+            if !a.function || !b.function || a.stage != b.stage {
+                continue;
+            }
+            let (Some(x), Some(y)) = (&a.access, &b.access) else {
+                continue;
+            };
+
+            let types = x.conflicts(y);
+            if !types.is_empty() && !precedes(systems, i, j) && !precedes(systems, j, i) {
+                return Err(ScheduleError::Conflict {
+                    systems: (a.name(), b.name()),
+                    types,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether the system at `from` has to run before the one at `to`
+fn precedes<E>(systems: &[Scheduled<E>], from: usize, to: usize) -> bool {
+    let mut seen = vec![false; systems.len()];
+    let mut stack = vec![from];
+    while let Some(i) = stack.pop() {
+        if std::mem::replace(&mut seen[i], true) {
+            continue;
+        }
+        for (j, system) in systems.iter().enumerate() {
+            if systems[i].runs_before(system) {
+                if j == to {
+                    return true;
+                }
+                stack.push(j);
+            }
+        }
+    }
+    false
+}
+
+/// Splits the order into runs of systems that can run at the same time,
+/// systems join the current batch unless they conflict with or are ordered
+/// relative to a system already in it, or didn't declare their access.
+#[cfg(feature = "parallel")]
+pub(crate) fn batches<E>(systems: &[Scheduled<E>], order: &[usize]) -> Vec<Vec<usize>> {
+    let mut batches: Vec<Vec<usize>> = Vec::new();
+    for &i in order {
+        let system = &systems[i];
+        let fits = batches.last().is_some_and(|batch| {
+            batch.iter().all(|&j| {
+                let other = &systems[j];
+                match (&system.access, &other.access) {
+                    (Some(a), Some(b)) => {
+                        other.stage == system.stage
+                            && a.conflicts(b).is_empty()
+                            && !other.runs_before(system)
+                            && !system.runs_before(other)
+                    }
+                    _ => false,
+                }
+            })
+        });
+
+        match batches.last_mut() {
+            Some(batch) if fits => batch.push(i),
+            _ => batches.push(vec![i]),
+        }
+    }
+    batches
+}
+
+/// Every remaining system has a predecessor that also remains, so walking
+/// backwards through predecessors must eventually revisit a system.
+fn cycle<E>(systems: &[Scheduled<E>], remaining: &[usize]) -> Vec<&'static str> {
+    let mut path = vec![remaining[0]];
+    loop {
+        let current = *path.last().unwrap();
+        let previous = *remaining
+            .iter()
+            .find(|i| systems[**i].runs_before(&systems[current]))
+            .unwrap();
+
+        if let Some(start) = path.iter().position(|i| *i == previous) {
+            let mut cycle = path[start..]
+                .iter()
+                .rev()
+                .map(|i| systems[*i].name())
+                .collect::<Vec<_>>();
+            cycle.insert(0, systems[previous].name());
+            return cycle;
+        }
+        path.push(previous);
+    }
+}
+
+/// Run condition that passes while the resource exists and equals `value`
+pub fn resource_equals<E, T: PartialEq + MaybeSync + 'static>(
+    value: T,
+) -> impl Fn(&World<E>) -> bool + MaybeSync {
+    move |world| world.get::<T>().is_some_and(|resource| *resource == value)
+}
+
+/// Run condition that passes when the resource has changed since the system
+/// last ran
+pub fn resource_changed<E, T: 'static>() -> impl Fn(&World<E>) -> bool + MaybeSync {
+    |world| world.resource_changed::<T>()
+}
+
+/// Run condition that passes while the resource exists
+pub fn resource_exists<E, T: 'static>() -> impl Fn(&World<E>) -> bool + MaybeSync {
+    |world| world.get::<T>().is_some()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sync::RefCell;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum State {
+        Running,
+        Paused,
+    }
+
+    type Log = RefCell<Vec<&'static str>>;
+
+    fn log(name: &'static str) -> impl Fn(&World<()>) {
+        move |world| world.get::<Log>().unwrap().borrow_mut().push(name)
+    }
+
+    fn log_name() -> &'static str {
+        std::any::type_name_of_val(&log("a"))
+    }
+
+    fn ran(world: &World<()>) -> Vec<&'static str> {
+        world.get::<Log>().unwrap().borrow_mut().drain(..).collect()
+    }
+
+    #[test]
+    pub fn test_stages() {
+        let world = World::<()>::new()
+            .with_resource(Log::default())
+            .with_ticker(log("render"))
+            .in_stage(Stage::Render)
+            .with_ticker(log("update"))
+            .with_ticker(log("pre"))
+            .in_stage(Stage::PreUpdate)
+            .with_ticker(log("post"))
+            .in_stage(Stage::PostUpdate)
+            .with_ticker(log("update 2"));
+
+        world.tick();
+        assert_eq!(
+            ran(&world),
+            vec!["pre", "update", "update 2", "post", "render"]
+        );
+    }
+
+    #[test]
+    pub fn test_ordering() {
+        let world = World::<()>::new()
+            .with_resource(Log::default())
+            .with_ticker(log("c"))
+            .label("c")
+            .after("b")
+            .with_ticker(log("a"))
+            .label("a")
+            .with_ticker(log("b"))
+            .label("b")
+            .after("a")
+            .with_ticker(log("first"))
+            .before("a");
+
+        world.tick();
+        assert_eq!(ran(&world), vec!["first", "a", "b", "c"]);
+    }
+
+    #[test]
+    pub fn test_cycle() {
+        let world = World::<()>::new()
+            .with_ticker(log("a"))
+            .label("a")
+            .after("c")
+            .with_ticker(log("b"))
+            .label("b")
+            .after("a")
+            .with_ticker(log("c"))
+            .label("c")
+            .after("b")
+            .with_ticker(log("d"))
+            .before("a");
+
+        let Err(ScheduleError::Cycle(cycle)) = world.schedule() else {
+            panic!("Expected a cycle");
+        };
+        assert_eq!(cycle, vec!["a", "b", "c", "a"]);
+
+        let world = World::<()>::new()
+            .with_ticker(log("a"))
+            .label("a")
+            .in_stage(Stage::Render)
+            .with_ticker(log("b"))
+            .after("a");
+        assert_eq!(
+            world.schedule(),
+            Err(ScheduleError::StageOrder {
+                before: ("a", Stage::Render),
+                after: (log_name(), Stage::Update),
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_unknown_label() {
+        let world = World::<()>::new()
+            .with_ticker(log("a"))
+            .label("a")
+            .with_ticker(log("b"))
+            .after("a")
+            .before("missing");
+        assert_eq!(
+            world.schedule(),
+            Err(ScheduleError::UnknownLabel {
+                system: log_name(),
+                label: "missing",
+            })
+        );
+    }
+
+    #[test]
+    pub fn test_run_if() {
+        let world = World::<()>::new()
+            .with_resource(Log::default())
+            .with_resource(State::Running)
+            .with_ticker(log("running"))
+            .run_if(resource_equals(State::Running))
+            .with_ticker(log("paused"))
+            .run_if(resource_equals(State::Paused))
+            .with_ticker(log("missing"))
+            .run_if(resource_exists::<(), u32>())
+            .with_ticker(log("always"));
+
+        world.tick();
+        assert_eq!(ran(&world), vec!["running", "always"]);
+
+        *world.get_mut::<State>().unwrap() = State::Paused;
+        world.tick();
+        assert_eq!(ran(&world), vec!["paused", "always"]);
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    pub fn test_batches() {
+        fn assert_sync<T: Send + Sync>() {}
+        assert_sync::<World<()>>();
+
+        let world = World::<()>::new()
+            .with_ticker(log("a"))
+            .reads::<u32>()
+            .with_ticker(log("b"))
+            .reads::<u32>()
+            .writes::<f32>()
+            .with_ticker(log("c"))
+            .writes::<u32>()
+            .with_ticker(log("d"))
+            .label("d")
+            .reads::<u32>()
+            .with_ticker(log("e"))
+            .after("d")
+            .reads::<f32>()
+            .with_ticker(log("exclusive"))
+            .with_ticker(log("f"))
+            .reads::<u32>()
+            .with_ticker(log("render"))
+            .in_stage(Stage::Render)
+            .reads::<u32>();
+
+        assert_eq!(
+            batches(&world.systems, &world.order()),
+            vec![
+                vec![0, 1],
+                vec![2],
+                vec![3],
+                vec![4],
+                vec![5],
+                vec![6],
+                vec![7]
+            ]
+        );
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    pub fn test_tick_parallel() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        #[derive(Default)]
+        struct Running {
+            now: AtomicUsize,
+            most: AtomicUsize,
+        }
+
+        fn run(world: &World<()>) {
+            let running = world.get::<Running>().unwrap();
+            let now = running.now.fetch_add(1, Ordering::SeqCst) + 1;
+            running.most.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            running.now.fetch_sub(1, Ordering::SeqCst);
+        }
+
+        let world = World::<()>::new()
+            .with_resource(Running::default())
+            .with_resource(Log::default())
+            .with_ticker(run)
+            .reads_resource::<Running>()
+            .reads::<u32>()
+            .with_ticker(run)
+            .reads_resource::<Running>()
+            .reads::<f32>()
+            .with_ticker(log("a"))
+            .writes_resource::<Log>()
+            .with_ticker(log("b"))
+            .writes_resource::<Log>();
+
+        world.tick_parallel();
+        assert_eq!(
+            world.get::<Running>().unwrap().most.load(Ordering::SeqCst),
+            rayon::current_num_threads().min(2)
+        );
+        assert_eq!(ran(&world), vec!["a", "b"]);
+
+        let world = World::<()>::new()
+            .with_resource(Running::default())
+            .with_ticker(run)
+            .reads_resource::<Running>()
+            .writes::<u32>()
+            .with_ticker(run)
+            .reads_resource::<Running>()
+            .reads::<u32>();
+
+        world.tick_parallel();
+        assert_eq!(
+            world.get::<Running>().unwrap().most.load(Ordering::SeqCst),
+            1
+        );
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    any::{Any, TypeId},
+    collections::{HashMap, HashSet, VecDeque},
+};
+
+use crate::{
+    scene::PrefabInstance, sync::Shared, Archetype, Children, EntityId, MaybeSync, Parent,
+    RowIndex, TableId, World,
+};
+
+/// Old and new ids of entities brought back by a restore
+type Restored = HashMap<EntityId, EntityId>;
+
+/// Part of a [`Snapshot`] that can write itself back into a world
+trait Restore<E>: MaybeSync {
+    /// Despawns the entities the part covers, called on every part before
+    /// any is restored so the ids they had are free again
+    fn clear(&self, _world: &World<E>) {}
+    /// Ids of the entities the part brings back
+    fn entities(&self) -> Vec<EntityId> {
+        Vec::new()
+    }
+    /// Writes the part back, spawning entities with the ids in `restored`
+    fn restore(&self, world: &World<E>, restored: &Restored);
+}
+
+/// Components inserted on top of archetypes, put back onto the entities the
+/// archetype parts restored
+trait RestoreInserted<E>: MaybeSync {
+    fn restore(&self, world: &World<E>, restored: &Restored);
+}
+
+type Capture<E> = fn(&World<E>) -> Option<Shared<dyn Restore<E>>>;
+type CaptureInserted<E> = fn(&World<E>) -> Shared<dyn RestoreInserted<E>>;
+
+/// The archetypes, inserted components and resources a [`Snapshot`]
+/// captures. Hierarchy and prefab links are always captured.
+pub struct SnapshotFilter<E> {
+    captures: Vec<Capture<E>>,
+    inserted: Vec<CaptureInserted<E>>,
+}
+
+impl<E> Default for SnapshotFilter<E> {
+    fn default() -> Self {
+        Self {
+            captures: Vec::new(),
+            inserted: Vec::new(),
+        }
+        .component::<Parent>()
+        .component::<Children>()
+        .component::<PrefabInstance>()
+    }
+}
+
+impl<E> SnapshotFilter<E> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Captures every entity of `T` that still has all of its components.
+    /// Entities of `T` missing some are left as they are by a restore.
+    pub fn archetype<T: Archetype + Clone + MaybeSync>(mut self) -> Self {
+        self.captures.push(|world| {
+            // Tables missing components of the archetype can't be read back
+            let base = world.tables.get(world.archetypes[&TypeId::of::<T>()]);
+            let mut rows = Vec::new();
+            let mut skipped = HashSet::new();
+            world
+                .archetype_tables::<T>()
+                .into_iter()
+                .map(|table| world.tables.get(table))
+                .for_each(|table| {
+                    let entities = table.entities.borrow();
+                    if base
+                        .column_types()
+                        .all(|ty| table.column_types().any(|other| other == ty))
+                    {
+                        rows.extend(
+                            entities
+                                .iter()
+                                .enumerate()
+                                .map(|(row, id)| (*id, T::get(table, RowIndex(row as u32)))),
+                        );
+                    } else {
+                        skipped.extend(entities.iter().copied());
+                    }
+                });
+            Some(Shared::new(ArchetypeSnapshot { rows, skipped }))
+        });
+        self
+    }
+
+    /// Captures `T` on entities it was inserted onto, it is put back onto
+    /// the entities restored by the archetypes of the filter
+    pub fn component<T: Clone + MaybeSync + 'static>(mut self) -> Self {
+        self.inserted.push(|world| {
+            let values = if world.sparse.get::<T>().is_some() {
+                let ids = world
+                    .entities
+                    .lock()
+                    .iter()
+                    .map(|(id, _)| id)
+                    .collect::<Vec<_>>();
+                ids.into_iter()
+                    .filter_map(|id| Some((id, world.get_component::<T>(id)?.clone())))
+                    .collect()
+            } else {
+                world
+                    .tables
+                    .iter()
+                    .filter_map(|table| Some((table.entities.borrow(), table.column::<T>()?)))
+                    .flat_map(|(entities, column)| {
+                        entities
+                            .iter()
+                            .copied()
+                            .zip(column.iter().cloned())
+                            .collect::<Vec<_>>()
+                    })
+                    .collect()
+            };
+            Shared::new(InsertedSnapshot { values })
+        });
+        self
+    }
+
+    pub fn resource<T: Any + Clone + MaybeSync>(mut self) -> Self {
+        self.captures.push(|world| {
+            let resource = world.get::<T>()?.clone();
+            Some(Shared::new(ResourceSnapshot(resource)))
+        });
+        self
+    }
+}
+
+/// Owned copy of part of a world, taken with [`World::snapshot`]
+pub struct Snapshot<E> {
+    parts: Vec<Shared<dyn Restore<E>>>,
+    inserted: Vec<Shared<dyn RestoreInserted<E>>>,
+}
+
+impl<E> Clone for Snapshot<E> {
+    fn clone(&self) -> Self {
+        Self {
+            parts: self.parts.clone(),
+            inserted: self.inserted.clone(),
+        }
+    }
+}
+
+struct ArchetypeSnapshot<T> {
+    rows: Vec<(EntityId, T)>,
+    /// Entities of the archetype that were alive but couldn't be captured
+    skipped: HashSet<EntityId>,
+}
+
+impl<E, T: Archetype + Clone + MaybeSync> Restore<E> for ArchetypeSnapshot<T> {
+    fn clear(&self, world: &World<E>) {
+        let current = world
+            .archetype_tables::<T>()
+            .into_iter()
+            .flat_map(|table| world.tables.get(table).entities.borrow().clone())
+            .filter(|id| !self.skipped.contains(id))
+            .collect::<Vec<_>>();
+        current.into_iter().for_each(|id| {
+            let _ = world.despawn(id);
+        });
+    }
+
+    fn entities(&self) -> Vec<EntityId> {
+        self.rows.iter().map(|(id, _)| *id).collect()
+    }
+
+    fn restore(&self, world: &World<E>, restored: &Restored) {
+        self.rows
+            .iter()
+            .for_each(|(id, row)| world.spawn_at(restored[id], row.clone()));
+    }
+}
+
+struct InsertedSnapshot<T> {
+    values: HashMap<EntityId, T>,
+}
+
+impl<E, T: Clone + MaybeSync + 'static> RestoreInserted<E> for InsertedSnapshot<T> {
+    fn restore(&self, world: &World<E>, restored: &Restored) {
+        restored.iter().for_each(|(old, new)| {
+            if let Some(value) = self.values.get(old) {
+                let _ = world.insert_component(*new, value.clone());
+            }
+        });
+    }
+}
+
+struct ResourceSnapshot<T>(T);
+
+impl<E, T: Any + Clone + MaybeSync> Restore<E> for ResourceSnapshot<T> {
+    fn restore(&self, world: &World<E>, _: &Restored) {
+        if let Some(mut resource) = world.get_mut::<T>() {
+            *resource = self.0.clone();
+        }
+    }
+}
+
+impl<E> World<E> {
+    /// Copies the archetypes, components and resources selected by `filter`
+    pub fn snapshot(&self, filter: &SnapshotFilter<E>) -> Snapshot<E> {
+        Snapshot {
+            parts: filter
+                .captures
+                .iter()
+                .filter_map(|capture| capture(self))
+                .collect(),
+            inserted: filter
+                .inserted
+                .iter()
+                .map(|capture| capture(self))
+                .collect(),
+        }
+    }
+
+    /// Puts the world back the way it was when `snapshot` was taken. Entities
+    /// of the captured archetypes are replaced by the captured ones, which
+    /// keep their old ids unless an entity the snapshot doesn't cover has
+    /// taken the slot since. Those get new ids, returned as `(old, new)`.
+    pub fn restore(&self, snapshot: &Snapshot<E>) -> Vec<(EntityId, EntityId)> {
+        snapshot.parts.iter().for_each(|part| part.clear(self));
+
+        // Every old id is reclaimed before any new one is handed out, so an
+        // entity can't lose its id to another one being restored
+        let ids = snapshot
+            .parts
+            .iter()
+            .flat_map(|part| part.entities())
+            .collect::<Vec<_>>();
+        let mut entities = self.entities.lock();
+        let reclaimed = ids
+            .iter()
+            .map(|id| entities.reclaim(*id))
+            .collect::<Vec<_>>();
+        let restored = ids
+            .into_iter()
+            .zip(reclaimed)
+            .map(|(id, reclaimed)| (id, if reclaimed { id } else { entities.reserve() }))
+            .collect::<Restored>();
+        drop(entities);
+
+        snapshot
+            .parts
+            .iter()
+            .for_each(|part| part.restore(self, &restored));
+        snapshot
+            .inserted
+            .iter()
+            .for_each(|part| part.restore(self, &restored));
+        self.relink(&restored);
+
+        restored
+            .into_iter()
+            .filter(|(old, new)| old != new)
+            .collect()
+    }
+
+    /// Points hierarchy links between restored entities at their new ids
+    fn relink(&self, restored: &Restored) {
+        if restored.iter().all(|(old, new)| old == new) {
+            return;
+        }
+        restored.values().for_each(|id| {
+            if let Some(mut parent) = self.get_component_mut::<Parent>(*id) {
+                if let Some(new) = restored.get(&parent.0) {
+                    parent.0 = *new;
+                }
+            }
+            if let Some(mut children) = self.get_component_mut::<Children>(*id) {
+                children
+                    .0
+                    .iter_mut()
+                    .for_each(|child| *child = *restored.get(child).unwrap_or(child));
+            }
+        });
+    }
+
+    /// Every table holding entities of `T`
+    fn archetype_tables<T: Archetype>(&self) -> Vec<TableId> {
+        self.signatures
+            .lock()
+            .iter()
+            .filter(|((archetype, _), _)| *archetype == TypeId::of::<T>())
+            .map(|(_, table)| *table)
+            .collect()
+    }
+}
+
+/// The last few snapshots keyed by tick, for rolling back to an earlier tick
+/// and simulating forward again
+pub struct SnapshotBuffer<K, E> {
+    capacity: usize,
+    snapshots: VecDeque<(K, Snapshot<E>)>,
+}
+
+impl<K: Copy + PartialEq, E> SnapshotBuffer<K, E> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            snapshots: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Adds a snapshot, dropping the oldest if the buffer is full
+    pub fn push(&mut self, tick: K, snapshot: Snapshot<E>) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back((tick, snapshot));
+    }
+
+    pub fn get(&self, tick: K) -> Option<&Snapshot<E>> {
+        self.snapshots
+            .iter()
+            .find(|(other, _)| *other == tick)
+            .map(|(_, snapshot)| snapshot)
+    }
+
+    /// Restores the snapshot taken at `tick` and drops the ones taken after
+    /// it, returning the ids that changed as [`World::restore`] does. `None`
+    /// if it has already been dropped.
+    pub fn rollback(&mut self, world: &World<E>, tick: K) -> Option<Vec<(EntityId, EntityId)>> {
+        let index = self
+            .snapshots
+            .iter()
+            .position(|(other, _)| *other == tick)?;
+        self.snapshots.truncate(index + 1);
+        Some(world.restore(&self.snapshots[index].1))
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::*, scene::Scene};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Archetype, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Thing {
+        value: u32,
+    }
+
+    #[derive(Archetype, Clone, Debug, PartialEq, Serialize, Deserialize)]
+    struct Other {
+        name: String,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Score(u32);
+
+    fn save(world: &World<()>) -> Vec<u8> {
+        let mut scene = Scene::default();
+        scene.from_world(world);
+        let mut buffer = Vec::new();
+        scene
+            .save(world, &mut serde_json::Serializer::new(&mut buffer))
+            .unwrap();
+        buffer
+    }
+
+    #[test]
+    pub fn test_restore() {
+        let world = World::<()>::new()
+            .register::<Thing>()
+            .register::<Other>()
+            .with_resource(Score(0));
+        let ids = (0..4)
+            .map(|value| world.spawn(Thing { value }))
+            .collect::<Vec<_>>();
+        world.spawn(Other {
+            name: String::from("a"),
+        });
+        world.remove_component::<u32>(ids[3]).unwrap();
+
+        let filter = SnapshotFilter::new()
+            .archetype::<Thing>()
+            .resource::<Score>();
+        let snapshot = world.snapshot(&filter);
+        let saved = save(&world);
+
+        *world.get_component_mut::<u32>(ids[0]).unwrap() = 10;
+        world.despawn(ids[1]).unwrap();
+        let spawned = world.spawn(Thing { value: 5 });
+        assert_eq!(spawned.index(), ids[1].index());
+        world.insert_component(ids[2], 0.5_f32).unwrap();
+        world.get_mut::<Score>().unwrap().0 = 3;
+
+        assert_eq!(world.restore(&snapshot.clone()), Vec::new());
+        assert_eq!(save(&world), saved);
+        assert!(world.is_alive(ids[1]));
+        assert!(!world.is_alive(spawned));
+        // Couldn't be captured, so it is left alone
+        assert!(world.is_alive(ids[3]));
+        assert_eq!(world.get_component::<f32>(ids[2]).as_deref(), None);
+        assert_eq!(*world.get::<Score>().unwrap(), Score(0));
+    }
+
+    #[test]
+    pub fn test_generations() {
+        let world = World::<()>::new().register::<Thing>();
+        let a = world.spawn(Thing { value: 0 });
+        let snapshot = world.snapshot(&SnapshotFilter::new().archetype::<Thing>());
+        world.despawn(a).unwrap();
+        let b = world.spawn(Thing { value: 1 });
+        assert_eq!(b.index(), a.index());
+
+        world.restore(&snapshot);
+        assert!(world.is_alive(a));
+        assert!(!world.is_alive(b));
+
+        // Ids handed out after the restore move past every earlier one
+        world.despawn(a).unwrap();
+        let c = world.spawn(Thing { value: 2 });
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert_ne!(c, b);
+        assert!(!world.is_alive(b));
+        assert!(world.get_component::<u32>(b).is_none());
+    }
+
+    #[test]
+    pub fn test_uncovered() {
+        let world = World::<()>::new().register::<Thing>().register::<Other>();
+        let a = world.spawn(Thing { value: 0 });
+        let child = world.spawn(Thing { value: 1 });
+        world.set_parent(child, a).unwrap();
+        let snapshot = world.snapshot(&SnapshotFilter::new().archetype::<Thing>());
+
+        world.despawn(a).unwrap();
+        let other = world.spawn(Other {
+            name: String::from("other"),
+        });
+        assert_eq!(other.index(), a.index());
+
+        let remapped = world.restore(&snapshot);
+        assert!(world.is_alive(other));
+        assert_eq!(remapped.len(), 1);
+        let (old, new) = remapped[0];
+        assert_eq!(old, a);
+        assert_eq!(world.get_component::<u32>(new).as_deref(), Some(&0));
+        assert_eq!(world.parent(child), Some(new));
+        assert_eq!(world.children(new), vec![child]);
+    }
+
+    #[test]
+    pub fn test_buffer() {
+        let world = World::<()>::new().with_resource(Score(0));
+        let filter = SnapshotFilter::new().resource::<Score>();
+        let mut buffer = SnapshotBuffer::new(3);
+        for tick in 0..5_u64 {
+            world.get_mut::<Score>().unwrap().0 = tick as u32;
+            buffer.push(tick, world.snapshot(&filter));
+        }
+        assert_eq!(buffer.len(), 3);
+        assert!(buffer.get(1).is_none());
+        assert!(buffer.rollback(&world, 1).is_none());
+
+        assert_eq!(buffer.rollback(&world, 3), Some(Vec::new()));
+        assert_eq!(*world.get::<Score>().unwrap(), Score(3));
+        assert!(buffer.get(4).is_none());
+        assert_eq!(buffer.len(), 2);
+    }
+}
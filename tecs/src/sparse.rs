@@ -0,0 +1,516 @@
+//! Sparse-set storage for components that are inserted and removed often,
+//! like markers, so toggling them doesn't move the entity between tables.
+//! Components of a type registered with [`World::with_sparse`] are stored
+//! outside of tables and queried with [`Sparse`].
+
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    marker::PhantomData,
+    ptr::NonNull,
+};
+
+use crate::{
+    sync::{filter_map_mut, filter_map_ref, Ref, RefCell, RefMut},
+    Access, ComponentMut, ComponentTicks, EntityId, Fetch, Join, MaybeSync, Mut, Query, Table,
+    Ticks, World,
+};
+
+/// Components of one type densely packed in no particular order, with the
+/// index of each entity's component looked up by entity index
+pub struct SparseSet<T> {
+    entities: RefCell<Vec<EntityId>>,
+    /// Position in `values` of the component of each entity index
+    rows: RefCell<Vec<Option<u32>>>,
+    values: RefCell<Vec<T>>,
+    ticks: RefCell<Vec<ComponentTicks>>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            entities: RefCell::default(),
+            rows: RefCell::default(),
+            values: RefCell::default(),
+            ticks: RefCell::default(),
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    fn row(&self, entity: EntityId) -> Option<usize> {
+        lookup(&self.rows.borrow(), &self.entities.borrow(), entity)
+    }
+
+    pub fn contains(&self, entity: EntityId) -> bool {
+        self.row(entity).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, entity: EntityId) -> Option<Ref<'_, T>> {
+        let row = self.row(entity)?;
+        filter_map_ref(self.values.borrow(), |values| values.get(row))
+    }
+
+    pub fn get_mut(&self, entity: EntityId, tick: u32) -> Option<ComponentMut<'_, T>> {
+        let row = self.row(entity)?;
+        Some(ComponentMut {
+            value: filter_map_mut(self.values.borrow_mut(), |values| values.get_mut(row))?,
+            ticks: filter_map_ref(self.ticks.borrow(), |ticks| ticks.get(row))?,
+            tick,
+        })
+    }
+
+    /// Adds the component as added at `tick`, replacing it if the entity
+    /// already has one
+    pub(crate) fn insert(&self, entity: EntityId, value: T, tick: u32) {
+        if let Some(row) = self.row(entity) {
+            self.values.borrow_mut()[row] = value;
+            self.ticks.borrow()[row].changed.set(tick);
+            return;
+        }
+
+        let mut rows = self.rows.borrow_mut();
+        let index = entity.index() as usize;
+        if rows.len() <= index {
+            rows.resize(index + 1, None);
+        }
+        rows[index] = Some(self.len() as u32);
+        self.entities.borrow_mut().push(entity);
+        self.values.borrow_mut().push(value);
+        self.ticks.borrow_mut().push(ComponentTicks::new(tick));
+    }
+
+    /// Swap removes the component, moving the last one into its place
+    pub(crate) fn remove(&self, entity: EntityId) -> Option<T> {
+        let row = self.row(entity)?;
+        let mut rows = self.rows.borrow_mut();
+        let mut entities = self.entities.borrow_mut();
+        rows[entity.index() as usize] = None;
+        entities.swap_remove(row);
+        if let Some(moved) = entities.get(row) {
+            rows[moved.index() as usize] = Some(row as u32);
+        }
+        self.ticks.borrow_mut().swap_remove(row);
+        Some(self.values.borrow_mut().swap_remove(row))
+    }
+}
+
+/// Type erased operations on a [`SparseSet`]
+trait AnySparseSet: MaybeSync {
+    fn as_any(&self) -> &dyn Any;
+    fn contains(&self, entity: EntityId) -> bool;
+    fn despawn(&self, entity: EntityId);
+}
+
+impl<T: MaybeSync + 'static> AnySparseSet for SparseSet<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn contains(&self, entity: EntityId) -> bool {
+        SparseSet::contains(self, entity)
+    }
+
+    fn despawn(&self, entity: EntityId) {
+        self.remove(entity);
+    }
+}
+
+/// The sparse set of every component type registered as sparse
+#[derive(Default)]
+pub struct SparseSets {
+    sets: HashMap<TypeId, Box<dyn AnySparseSet>>,
+}
+
+impl SparseSets {
+    pub fn get<T: 'static>(&self) -> Option<&SparseSet<T>> {
+        self.sets
+            .get(&TypeId::of::<T>())
+            .map(|set| set.as_any().downcast_ref().unwrap())
+    }
+
+    fn expect<T: 'static>(&self) -> &SparseSet<T> {
+        self.get().unwrap_or_else(|| {
+            panic!(
+                "{} isn't stored sparsely, it may need registering with `with_sparse`",
+                type_name::<T>()
+            )
+        })
+    }
+
+    /// Component types stored sparsely for `entity`
+    pub(crate) fn types(&self, entity: EntityId) -> impl Iterator<Item = TypeId> + '_ {
+        self.sets
+            .iter()
+            .filter(move |(_, set)| set.contains(entity))
+            .map(|(ty, _)| *ty)
+    }
+
+    pub(crate) fn despawn(&self, entity: EntityId) {
+        self.sets.values().for_each(|set| set.despawn(entity))
+    }
+}
+
+impl<E> World<E> {
+    /// Stores `T` in a sparse set instead of in tables, inserting and
+    /// removing it no longer moves the entity but it has to be queried with
+    /// [`Sparse`]
+    pub fn with_sparse<T: MaybeSync + 'static>(mut self) -> Self {
+        self.sparse
+            .sets
+            .insert(TypeId::of::<T>(), Box::new(SparseSet::<T>::default()));
+        self.inserted.get_mut().insert(
+            TypeId::of::<T>(),
+            crate::reflect::Component::inserted::<T>(),
+        );
+        self
+    }
+}
+
+/// Queries a component stored in a sparse set, yielding an `Option` for every
+/// entity the rest of the query matches
+pub struct Sparse<T>(PhantomData<T>);
+
+/// The sparse components of the entities in each table of a query
+pub struct SparseColumn<'a, T> {
+    entities: Vec<Ref<'a, [EntityId]>>,
+    rows: Ref<'a, [Option<u32>]>,
+    /// The entity each row of the set belongs to
+    owners: Ref<'a, [EntityId]>,
+    values: Ref<'a, [T]>,
+}
+
+impl<T> SparseColumn<'_, T> {
+    pub fn iter(&self) -> impl Iterator<Item = Option<&T>> {
+        self.entities
+            .iter()
+            .flat_map(|entities| entities.iter())
+            .map(|entity| {
+                let row = lookup(&self.rows, &self.owners, *entity)?;
+                Some(&self.values[row])
+            })
+    }
+}
+
+impl<E, T: MaybeSync + 'static> Query<E> for Sparse<&'_ T> {
+    type Output<'a> = SparseColumn<'a, T>;
+
+    fn filter(_: &(TypeId, &Table)) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        access.read::<T>()
+    }
+
+    fn data<'a>(
+        tables: &[(TypeId, &'a Table)],
+        sparse: &'a SparseSets,
+        _ticks: Ticks,
+    ) -> Self::Output<'a> {
+        let set = sparse.expect::<T>();
+        SparseColumn {
+            entities: entities(tables),
+            rows: Ref::map(set.rows.borrow(), |rows| rows.as_slice()),
+            owners: Ref::map(set.entities.borrow(), |entities| entities.as_slice()),
+            values: Ref::map(set.values.borrow(), |values| values.as_slice()),
+        }
+    }
+}
+
+/// Mutable borrows of the sparse components of the entities in each table of
+/// a query, components are only marked changed when written to
+pub struct SparseColumnMut<'a, T> {
+    entities: Vec<Ref<'a, [EntityId]>>,
+    rows: Ref<'a, [Option<u32>]>,
+    /// The entity each row of the set belongs to
+    owners: Ref<'a, [EntityId]>,
+    values: RefMut<'a, [T]>,
+    ticks: Ref<'a, [ComponentTicks]>,
+    tick: u32,
+}
+
+impl<T> SparseColumnMut<'_, T> {
+    pub fn iter(&self) -> impl Iterator<Item = Option<&T>> {
+        self.entities
+            .iter()
+            .flat_map(|entities| entities.iter())
+            .map(|entity| {
+                let row = lookup(&self.rows, &self.owners, *entity)?;
+                Some(&self.values[row])
+            })
+    }
+
+    pub fn get_mut(&mut self, entity: EntityId) -> Option<Mut<'_, T>> {
+        let row = lookup(&self.rows, &self.owners, entity)?;
+        Some(Mut {
+            value: &mut self.values[row],
+            ticks: &self.ticks[row],
+            tick: self.tick,
+        })
+    }
+}
+
+impl<E, T: MaybeSync + 'static> Query<E> for Sparse<&'_ mut T> {
+    type Output<'a> = SparseColumnMut<'a, T>;
+
+    fn filter(_: &(TypeId, &Table)) -> bool {
+        true
+    }
+
+    fn access(access: &mut Access) {
+        access.write::<T>()
+    }
+
+    fn data<'a>(
+        tables: &[(TypeId, &'a Table)],
+        sparse: &'a SparseSets,
+        ticks: Ticks,
+    ) -> Self::Output<'a> {
+        let set = sparse.expect::<T>();
+        SparseColumnMut {
+            entities: entities(tables),
+            rows: Ref::map(set.rows.borrow(), |rows| rows.as_slice()),
+            owners: Ref::map(set.entities.borrow(), |entities| entities.as_slice()),
+            values: RefMut::map(set.values.borrow_mut(), |values| values.as_mut_slice()),
+            ticks: Ref::map(set.ticks.borrow(), |ticks| ticks.as_slice()),
+            tick: ticks.this_run,
+        }
+    }
+}
+
+/// The row of `entity` in a set, as long as the row belongs to it and not an
+/// earlier entity with the same index
+fn lookup(rows: &[Option<u32>], owners: &[EntityId], entity: EntityId) -> Option<usize> {
+    let row = (*rows.get(entity.index() as usize)?)? as usize;
+    (owners[row] == entity).then_some(row)
+}
+
+fn entities<'a>(tables: &[(TypeId, &'a Table)]) -> Vec<Ref<'a, [EntityId]>> {
+    tables
+        .iter()
+        .map(|(_, table)| Ref::map(table.entities.borrow(), |entities| entities.as_slice()))
+        .collect()
+}
+
+pub struct SparseFetch<'b, T> {
+    entities: Vec<&'b [EntityId]>,
+    rows: &'b [Option<u32>],
+    owners: &'b [EntityId],
+    values: &'b [T],
+}
+
+impl<'b, T> Fetch for SparseFetch<'b, T> {
+    type Item = Option<&'b T>;
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.entities.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.entities[table].len())
+    }
+
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+        let entity = self.entities[table][row];
+        let row = lookup(self.rows, self.owners, entity)?;
+        Some(&self.values[row])
+    }
+}
+
+impl<T> Join for SparseColumn<'_, T> {
+    type Fetch<'b>
+        = SparseFetch<'b, T>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        SparseFetch {
+            entities: self.entities.iter().map(|entities| &**entities).collect(),
+            rows: &self.rows,
+            owners: &self.owners,
+            values: &self.values,
+        }
+    }
+}
+
+pub struct SparseFetchMut<'b, T> {
+    entities: Vec<&'b [EntityId]>,
+    rows: &'b [Option<u32>],
+    owners: &'b [EntityId],
+    values: NonNull<T>,
+    ticks: &'b [ComponentTicks],
+    tick: u32,
+    _marker: PhantomData<&'b mut T>,
+}
+
+impl<'b, T> Fetch for SparseFetchMut<'b, T> {
+    type Item = Option<Mut<'b, T>>;
+
+    fn tables(&self) -> Option<usize> {
+        Some(self.entities.len())
+    }
+
+    fn rows(&self, table: usize) -> Option<usize> {
+        Some(self.entities[table].len())
+    }
+
+    unsafe fn get(&mut self, table: usize, row: usize) -> Self::Item {
+        let entity = self.entities[table][row];
+        let row = lookup(self.rows, self.owners, entity)?;
+        // Each entity has its own row, so rows are only fetched once
+        Some(Mut {
+            value: unsafe { &mut *self.values.as_ptr().add(row) },
+            ticks: &self.ticks[row],
+            tick: self.tick,
+        })
+    }
+}
+
+impl<T> Join for SparseColumnMut<'_, T> {
+    type Fetch<'b>
+        = SparseFetchMut<'b, T>
+    where
+        Self: 'b;
+
+    fn fetch(&mut self) -> Self::Fetch<'_> {
+        SparseFetchMut {
+            entities: self.entities.iter().map(|entities| &**entities).collect(),
+            rows: &self.rows,
+            owners: &self.owners,
+            values: NonNull::from(&mut *self.values).cast(),
+            ticks: &self.ticks,
+            tick: self.tick,
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::*, EntityError};
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        value: u32,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Targeted;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Cooldown(u32);
+
+    fn world() -> World<()> {
+        World::new()
+            .register_unsaved::<Thing>()
+            .with_sparse::<Targeted>()
+            .with_sparse::<Cooldown>()
+    }
+
+    #[test]
+    pub fn test_sparse() {
+        let world = world();
+        let ids = (0..4)
+            .map(|value| world.spawn(Thing { value }))
+            .collect::<Vec<_>>();
+        world.insert_component(ids[1], Targeted).unwrap();
+        world.insert_component(ids[3], Targeted).unwrap();
+        world.insert_component(ids[2], Cooldown(5)).unwrap();
+
+        // Entities don't move when sparse components are inserted
+        let (values, targeted) = world.query::<(&u32, Sparse<&Targeted>)>();
+        assert_eq!(values.iter().copied().collect::<Vec<_>>(), vec![0, 1, 2, 3]);
+        assert_eq!(
+            targeted.iter().map(|x| x.is_some()).collect::<Vec<_>>(),
+            vec![false, true, false, true]
+        );
+        drop((values, targeted));
+
+        assert_eq!(world.remove_component::<Targeted>(ids[1]), Ok(Targeted));
+        assert_eq!(
+            world.remove_component::<Targeted>(ids[1]),
+            Err(EntityError::MissingComponent(ids[1]))
+        );
+        assert_eq!(
+            world.get_component::<Targeted>(ids[3]).as_deref(),
+            Some(&Targeted)
+        );
+
+        world.despawn(ids[3]).unwrap();
+        let reused = world.spawn(Thing { value: 4 });
+        assert_eq!(reused.index(), ids[3].index());
+        assert_eq!(world.get_component::<Targeted>(reused).as_deref(), None);
+    }
+
+    #[test]
+    pub fn test_sparse_mut() {
+        let world = world();
+        let ids = (0..3)
+            .map(|value| world.spawn(Thing { value }))
+            .collect::<Vec<_>>();
+        world.insert_component(ids[0], Cooldown(1)).unwrap();
+        world.insert_component(ids[2], Cooldown(3)).unwrap();
+
+        let mut query = world.query::<(&mut u32, Sparse<&mut Cooldown>)>();
+        query.iter_mut().for_each(|(mut value, cooldown)| {
+            if let Some(mut cooldown) = cooldown {
+                cooldown.0 -= 1;
+                *value += 10;
+            }
+        });
+        drop(query);
+
+        assert_eq!(
+            world.get_component::<Cooldown>(ids[0]).as_deref(),
+            Some(&Cooldown(0))
+        );
+        assert_eq!(world.get_component::<u32>(ids[1]).as_deref(), Some(&1));
+        assert_eq!(world.get_component::<u32>(ids[2]).as_deref(), Some(&12));
+        world.get_component_mut::<Cooldown>(ids[2]).unwrap().0 = 7;
+        assert_eq!(
+            world.get_component::<Cooldown>(ids[2]).as_deref(),
+            Some(&Cooldown(7))
+        );
+    }
+
+    #[test]
+    pub fn test_stale() {
+        let world = world();
+        let stale = world.spawn(Thing { value: 0 });
+        world.despawn(stale).unwrap();
+        let reused = world.spawn(Thing { value: 1 });
+        assert_eq!(reused.index(), stale.index());
+
+        // A component left behind by the entity that used the index before
+        world
+            .sparse
+            .expect::<Cooldown>()
+            .insert(stale, Cooldown(5), 0);
+
+        let (_, cooldowns) = world.query::<(&u32, Sparse<&Cooldown>)>();
+        assert_eq!(cooldowns.iter().collect::<Vec<_>>(), vec![None]);
+        drop(cooldowns);
+
+        let mut query = world.query::<(&u32, Sparse<&Cooldown>)>();
+        assert!(query.iter_mut().all(|(_, cooldown)| cooldown.is_none()));
+        drop(query);
+
+        let (_, mut cooldowns) = world.query::<(&u32, Sparse<&mut Cooldown>)>();
+        assert!(cooldowns.iter().all(|cooldown| cooldown.is_none()));
+        assert!(cooldowns.get_mut(reused).is_none());
+        drop(cooldowns);
+
+        let mut query = world.query::<(&u32, Sparse<&mut Cooldown>)>();
+        assert!(query.iter_mut().all(|(_, cooldown)| cooldown.is_none()));
+    }
+}
@@ -0,0 +1,49 @@
+use std::sync::RwLock;
+
+use crate::Table;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TableId(pub u32);
+
+/// Append-only list of tables. Tables are individually boxed and never
+/// removed, so references to them stay valid while new tables are pushed
+/// during migrations.
+#[derive(Default)]
+pub(crate) struct Tables {
+    tables: RwLock<Vec<*mut Table>>,
+}
+
+// SAFETY: the pointers are owned boxes, and tables only hold MaybeSync data
+#[cfg(feature = "parallel")]
+unsafe impl Send for Tables {}
+#[cfg(feature = "parallel")]
+unsafe impl Sync for Tables {}
+
+impl Tables {
+    pub fn push(&self, table: Table) -> TableId {
+        let table = Box::into_raw(Box::new(table));
+        let mut tables = self.tables.write().unwrap();
+        tables.push(table);
+        TableId(tables.len() as u32 - 1)
+    }
+
+    pub fn get(&self, id: TableId) -> &Table {
+        // SAFETY: tables are only freed when Tables is dropped
+        unsafe { &*self.tables.read().unwrap()[id.0 as usize] }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Table> {
+        let len = self.tables.read().unwrap().len();
+        (0..len).map(|i| self.get(TableId(i as u32)))
+    }
+}
+
+impl Drop for Tables {
+    fn drop(&mut self) {
+        self.tables
+            .get_mut()
+            .unwrap()
+            .drain(..)
+            .for_each(|table| drop(unsafe { Box::from_raw(table) }))
+    }
+}
@@ -0,0 +1,162 @@
+//! Shared ownership and interior mutability the world is built from. With the
+//! `parallel` feature these are swapped for their thread safe equivalents,
+//! making [`World`](crate::World) `Send + Sync`.
+
+#[cfg(not(feature = "parallel"))]
+pub use std::{
+    cell::{Cell, Ref, RefCell, RefMut},
+    rc::Rc as Shared,
+};
+
+#[cfg(feature = "parallel")]
+pub use atomic_refcell::{AtomicRef as Ref, AtomicRefCell as RefCell, AtomicRefMut as RefMut};
+#[cfg(feature = "parallel")]
+pub use std::sync::Arc as Shared;
+
+/// Bound on everything stored in the world, `Send + Sync` with the `parallel`
+/// feature and nothing without it.
+#[cfg(not(feature = "parallel"))]
+pub trait MaybeSync {}
+#[cfg(not(feature = "parallel"))]
+impl<T: ?Sized> MaybeSync for T {}
+
+#[cfg(feature = "parallel")]
+pub trait MaybeSync: Send + Sync {}
+#[cfg(feature = "parallel")]
+impl<T: ?Sized + Send + Sync> MaybeSync for T {}
+
+#[cfg(not(feature = "parallel"))]
+pub(crate) type DynResource = dyn std::any::Any;
+#[cfg(feature = "parallel")]
+pub(crate) type DynResource = dyn std::any::Any + Send + Sync;
+
+pub fn filter_map_ref<'b, T: ?Sized, U: ?Sized>(
+    orig: Ref<'b, T>,
+    f: impl FnOnce(&T) -> Option<&U>,
+) -> Option<Ref<'b, U>> {
+    #[cfg(not(feature = "parallel"))]
+    return Ref::filter_map(orig, f).ok();
+    #[cfg(feature = "parallel")]
+    return Ref::filter_map(orig, f);
+}
+
+pub fn filter_map_mut<'b, T: ?Sized, U: ?Sized>(
+    orig: RefMut<'b, T>,
+    f: impl FnOnce(&mut T) -> Option<&mut U>,
+) -> Option<RefMut<'b, U>> {
+    #[cfg(not(feature = "parallel"))]
+    return RefMut::filter_map(orig, f).ok();
+    #[cfg(feature = "parallel")]
+    return RefMut::filter_map(orig, f);
+}
+
+/// Exclusive access to `T`, a [`std::sync::Mutex`] with the `parallel`
+/// feature and a [`RefCell`] without it
+#[derive(Debug, Default)]
+pub struct Mutex<T> {
+    #[cfg(not(feature = "parallel"))]
+    inner: RefCell<T>,
+    #[cfg(feature = "parallel")]
+    inner: std::sync::Mutex<T>,
+}
+
+#[cfg(not(feature = "parallel"))]
+pub type MutexGuard<'a, T> = RefMut<'a, T>;
+#[cfg(feature = "parallel")]
+pub type MutexGuard<'a, T> = std::sync::MutexGuard<'a, T>;
+
+impl<T> Mutex<T> {
+    pub fn new(value: T) -> Self {
+        Self {
+            inner: value.into(),
+        }
+    }
+
+    /// Panics if already locked without the `parallel` feature, or if a
+    /// thread panicked while holding the lock with it
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        #[cfg(not(feature = "parallel"))]
+        return self.inner.borrow_mut();
+        #[cfg(feature = "parallel")]
+        return self.inner.lock().unwrap();
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        #[cfg(not(feature = "parallel"))]
+        return self.inner.get_mut();
+        #[cfg(feature = "parallel")]
+        return self.inner.get_mut().unwrap();
+    }
+}
+
+#[cfg(feature = "parallel")]
+pub use atomic::{Atomic, Cell};
+
+#[cfg(feature = "parallel")]
+mod atomic {
+    use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+    pub trait Atomic: Copy {
+        type Atomic;
+
+        fn new(value: Self) -> Self::Atomic;
+        fn load(atomic: &Self::Atomic) -> Self;
+        fn swap(atomic: &Self::Atomic, value: Self) -> Self;
+    }
+
+    macro_rules! impl_atomic {
+        ($ty:ty, $atomic:ty) => {
+            impl Atomic for $ty {
+                type Atomic = $atomic;
+
+                fn new(value: Self) -> Self::Atomic {
+                    <$atomic>::new(value)
+                }
+
+                fn load(atomic: &Self::Atomic) -> Self {
+                    atomic.load(Ordering::SeqCst)
+                }
+
+                fn swap(atomic: &Self::Atomic, value: Self) -> Self {
+                    atomic.swap(value, Ordering::SeqCst)
+                }
+            }
+        };
+    }
+
+    impl_atomic!(u32, AtomicU32);
+    impl_atomic!(usize, AtomicUsize);
+
+    /// Atomic stand in for [`std::cell::Cell`] over integers
+    pub struct Cell<T: Atomic>(T::Atomic);
+
+    impl<T: Atomic> Cell<T> {
+        pub fn new(value: T) -> Self {
+            Self(T::new(value))
+        }
+
+        pub fn get(&self) -> T {
+            T::load(&self.0)
+        }
+
+        pub fn set(&self, value: T) {
+            T::swap(&self.0, value);
+        }
+
+        pub fn replace(&self, value: T) -> T {
+            T::swap(&self.0, value)
+        }
+    }
+
+    impl<T: Atomic + Default> Default for Cell<T> {
+        fn default() -> Self {
+            Self::new(T::default())
+        }
+    }
+
+    impl<T: Atomic + std::fmt::Debug> std::fmt::Debug for Cell<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            f.debug_tuple("Cell").field(&self.get()).finish()
+        }
+    }
+}
@@ -0,0 +1,419 @@
+//! Function systems, plain functions whose parameters are fetched from the
+//! world before each run.
+//!
+//! ```ignore
+//! fn movement(keyboard: Res<Keyboard>, Query((mut transforms, _)): Query<(&mut Transform, Is<Player>)>) {
+//!     ...
+//! }
+//!
+//! World::new().with_fn_system(movement)
+//! ```
+//!
+//! The resources a system needs and the types it borrows are checked when
+//! the schedule is built, see [`World::schedule`].
+
+use std::{
+    any::{type_name, Any, TypeId},
+    ops::{Deref, DerefMut},
+};
+
+use crate::{
+    sync::{Ref, RefMut},
+    Access, Commands, MaybeSync, World,
+};
+
+/// A parameter of a function system
+pub trait SystemParam<E> {
+    type Item<'w>
+    where
+        E: 'w;
+
+    /// The components and resources the parameter borrows
+    fn access(_access: &mut Access) {}
+    /// Resources that have to exist for the parameter to be fetched
+    fn resources(_resources: &mut Vec<(TypeId, &'static str)>) {}
+    /// `None` skips the system for this run
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>>;
+}
+
+/// Shared borrow of a resource
+pub struct Res<'w, T> {
+    value: Ref<'w, T>,
+}
+
+impl<T> Deref for Res<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<E, T: Any> SystemParam<E> for Res<'_, T> {
+    type Item<'w>
+        = Res<'w, T>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        access.read_resource::<T>()
+    }
+
+    fn resources(resources: &mut Vec<(TypeId, &'static str)>) {
+        resources.push((TypeId::of::<T>(), type_name::<T>()))
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        Some(Res {
+            value: world.get::<T>().unwrap_or_else(|| missing::<T>()),
+        })
+    }
+}
+
+/// Mutable borrow of a resource, marking it changed
+pub struct ResMut<'w, T> {
+    value: RefMut<'w, T>,
+}
+
+impl<T> Deref for ResMut<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.value
+    }
+}
+
+impl<T> DerefMut for ResMut<'_, T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.value
+    }
+}
+
+impl<E, T: Any> SystemParam<E> for ResMut<'_, T> {
+    type Item<'w>
+        = ResMut<'w, T>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        access.write_resource::<T>()
+    }
+
+    fn resources(resources: &mut Vec<(TypeId, &'static str)>) {
+        resources.push((TypeId::of::<T>(), type_name::<T>()))
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        Some(ResMut {
+            value: world.get_mut::<T>().unwrap_or_else(|| missing::<T>()),
+        })
+    }
+}
+
+/// A resource that doesn't have to exist
+impl<E, T: Any> SystemParam<E> for Option<Res<'_, T>> {
+    type Item<'w>
+        = Option<Res<'w, T>>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        access.read_resource::<T>()
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        Some(world.get::<T>().map(|value| Res { value }))
+    }
+}
+
+impl<E, T: Any> SystemParam<E> for Option<ResMut<'_, T>> {
+    type Item<'w>
+        = Option<ResMut<'w, T>>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        access.write_resource::<T>()
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        Some(world.get_mut::<T>().map(|value| ResMut { value }))
+    }
+}
+
+/// Resources are checked before systems run, so this only happens if one is
+/// removed during a tick
+fn missing<T>() -> ! {
+    panic!(
+        "Resource {} was removed while systems needed it",
+        type_name::<T>()
+    )
+}
+
+/// The columns of a query, destructure it with `Query((a, b)): Query<(&A, &B)>`.
+/// Queries don't depend on the event type so they are fetched as
+/// `crate::Query<()>`.
+pub struct Query<'w, Q: crate::Query<()>>(pub Q::Output<'w>);
+
+impl<E, Q: crate::Query<()>> SystemParam<E> for Query<'_, Q> {
+    type Item<'w>
+        = Query<'w, Q>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        Q::access(access)
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        Some(Query(Q::data(
+            &world.matching(Q::filter),
+            &world.sparse,
+            world.ticks(),
+        )))
+    }
+}
+
+/// A query expecting exactly one entity, see [`World::query_one`]
+pub struct QueryOne<'w, Q: crate::QueryOne<()>>(pub Q::Output<'w>);
+
+impl<E, Q: crate::QueryOne<()>> SystemParam<E> for QueryOne<'_, Q> {
+    type Item<'w>
+        = QueryOne<'w, Q>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        Q::access(access)
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        let (table, row) = world.first_row::<(), Q>();
+        Some(QueryOne(Q::data(table, row, &world.sparse, world.ticks())))
+    }
+}
+
+/// A query expecting exactly one entity that skips the system when there
+/// isn't one, or there are several, instead of panicking like [`QueryOne`]
+pub struct Single<'w, Q: crate::QueryOne<()>>(pub Q::Output<'w>);
+
+impl<E, Q: crate::QueryOne<()>> SystemParam<E> for Single<'_, Q> {
+    type Item<'w>
+        = Single<'w, Q>
+    where
+        E: 'w;
+
+    fn access(access: &mut Access) {
+        Q::access(access)
+    }
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        let (table, row) = world.matching_one::<(), Q>().ok()?;
+        Some(Single(Q::data(table, row, &world.sparse, world.ticks())))
+    }
+}
+
+impl<E> SystemParam<E> for Commands<'_, E> {
+    type Item<'w>
+        = Commands<'w, E>
+    where
+        E: 'w;
+
+    fn fetch(world: &World<E>) -> Option<Self::Item<'_>> {
+        Some(world.commands())
+    }
+}
+
+/// A function whose parameters are all [`SystemParam`]s, `P` is the tuple of
+/// its parameter types
+pub trait SystemFn<E, P>: MaybeSync + 'static {
+    fn access(access: &mut Access);
+    fn resources(resources: &mut Vec<(TypeId, &'static str)>);
+    fn run(&self, world: &World<E>);
+}
+
+macro_rules! impl_system_fn {
+    ($($param:ident)*) => {
+        impl<E, F, $($param: SystemParam<E>),*> SystemFn<E, ($($param,)*)> for F
+        where
+            F: Fn($($param),*) + for<'w> Fn($($param::Item<'w>),*) + MaybeSync + 'static,
+        {
+            fn access(_access: &mut Access) {
+                $($param::access(_access);)*
+            }
+
+            fn resources(_resources: &mut Vec<(TypeId, &'static str)>) {
+                $($param::resources(_resources);)*
+            }
+
+            #[allow(non_snake_case, clippy::too_many_arguments)]
+            fn run(&self, _world: &World<E>) {
+                // Calling through a generic function picks the higher ranked
+                // `Fn` bound
+                fn call<$($param),*>(f: impl Fn($($param),*), $($param: $param),*) {
+                    f($($param),*)
+                }
+                $(let Some($param) = $param::fetch(_world) else { return };)*
+                call(self, $($param),*)
+            }
+        }
+    };
+}
+
+impl_system_fn!();
+impl_system_fn!(A);
+impl_system_fn!(A B);
+impl_system_fn!(A B C);
+impl_system_fn!(A B C D);
+impl_system_fn!(A B C D E1);
+impl_system_fn!(A B C D E1 F1);
+impl_system_fn!(A B C D E1 F1 G);
+impl_system_fn!(A B C D E1 F1 G H);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{prelude::*, ScheduleError};
+
+    #[derive(Debug, PartialEq)]
+    struct Speed(u32);
+
+    #[derive(Debug, PartialEq)]
+    struct Distance(u32);
+
+    #[derive(Archetype, Clone)]
+    struct Thing {
+        position: u32,
+    }
+
+    fn step(
+        speed: Res<Speed>,
+        mut distance: ResMut<Distance>,
+        Query(mut positions): Query<&mut u32>,
+    ) {
+        distance.0 += speed.0;
+        positions.for_each(|mut position| *position += speed.0);
+    }
+
+    #[test]
+    pub fn test_fn_system() {
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_fn_system(step)
+            .with_resource(Speed(2))
+            .with_resource(Distance(0));
+        let thing = world.spawn(Thing { position: 1 });
+
+        world.tick();
+        world.tick();
+        assert_eq!(*world.get::<Distance>().unwrap(), Distance(4));
+        assert_eq!(world.get_component::<u32>(thing).as_deref(), Some(&5));
+    }
+
+    #[test]
+    pub fn test_validate() {
+        let world = World::<()>::new()
+            .with_fn_system(step)
+            .with_resource(Speed(2));
+        assert_eq!(
+            world.schedule(),
+            Err(ScheduleError::MissingResource {
+                system: type_name_of(step),
+                resource: type_name::<Distance>(),
+            })
+        );
+
+        fn aliased(_: Res<Speed>, _: Option<ResMut<Speed>>) {}
+        let world = World::<()>::new()
+            .with_resource(Speed(2))
+            .with_fn_system(aliased);
+        assert_eq!(
+            world.schedule(),
+            Err(ScheduleError::Aliased {
+                system: type_name_of(aliased),
+                types: vec![type_name::<Speed>()],
+            })
+        );
+
+        // Resources don't share borrows with components of the same type
+        fn shared(_: Res<u32>, _: Query<&mut u32>) {}
+        let world = World::<()>::new()
+            .with_resource(0_u32)
+            .with_fn_system(shared);
+        assert_eq!(world.schedule(), Ok(()));
+    }
+
+    #[test]
+    pub fn test_conflict() {
+        fn accelerate(mut speed: ResMut<Speed>) {
+            speed.0 += 1;
+        }
+
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_resource(Speed(2))
+            .with_resource(Distance(0))
+            .with_fn_system(accelerate)
+            .with_fn_system(step);
+        assert_eq!(
+            world.schedule(),
+            Err(ScheduleError::Conflict {
+                systems: (type_name_of(accelerate), type_name_of(step)),
+                types: vec![type_name::<Speed>()],
+            })
+        );
+
+        // Ordering through another system is enough
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_resource(Speed(2))
+            .with_resource(Distance(0))
+            .with_fn_system(accelerate)
+            .label("accelerate")
+            .with_fn_system(step)
+            .after("between")
+            .with_ticker(|_| ())
+            .label("between")
+            .after("accelerate");
+        assert_eq!(world.schedule(), Ok(()));
+
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_resource(Speed(2))
+            .with_resource(Distance(0))
+            .with_fn_system(accelerate)
+            .in_stage(Stage::PreUpdate)
+            .with_fn_system(step);
+        assert_eq!(world.schedule(), Ok(()));
+    }
+
+    #[test]
+    pub fn test_single() {
+        fn follow(mut distance: ResMut<Distance>, Single(position): Single<&u32>) {
+            distance.0 = *position;
+        }
+
+        let world = World::<()>::new()
+            .register_unsaved::<Thing>()
+            .with_fn_system(follow)
+            .with_resource(Distance(0));
+        world.tick();
+        assert_eq!(*world.get::<Distance>().unwrap(), Distance(0));
+
+        let thing = world.spawn(Thing { position: 3 });
+        world.tick();
+        assert_eq!(*world.get::<Distance>().unwrap(), Distance(3));
+
+        world.spawn(Thing { position: 5 });
+        world.tick();
+        assert_eq!(*world.get::<Distance>().unwrap(), Distance(3));
+
+        world.despawn(thing).unwrap();
+        world.tick();
+        assert_eq!(*world.get::<Distance>().unwrap(), Distance(5));
+    }
+
+    fn type_name_of<T>(_: T) -> &'static str {
+        type_name::<T>()
+    }
+}
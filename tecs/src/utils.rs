@@ -1,13 +1,21 @@
-use std::{fmt::Display, time::{Duration, Instant}};
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::World;
+use crate::{
+    system::{Res, ResMut},
+    Stage, World,
+};
 
+/// Counts down in [`Time`], so it stops while time is paused and runs faster
+/// or slower with its scale
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Timer {
     #[serde(skip)]
-    start: Option<Instant>,
+    start: Option<Duration>,
     pub duration: Duration,
 }
 
@@ -19,17 +27,117 @@ impl Timer {
         }
     }
 
-    pub fn start(&mut self) {
-        self.start = Some(Instant::now())
+    pub fn start(&mut self, time: &Time) {
+        self.start = Some(time.elapsed())
     }
 
-    pub fn done(&self) -> bool {
+    /// Timers that were never started are done
+    pub fn done(&self, time: &Time) -> bool {
         self.start
-            .map(|start| start.elapsed() > self.duration)
+            .map(|start| time.elapsed().saturating_sub(start) > self.duration)
             .unwrap_or(true)
     }
 }
 
+/// Simulation time, only moved forward by [`Time::advance`] so the same
+/// deltas always give the same steps. Real deltas are scaled and split into
+/// fixed steps of `step`, with the remainder carried over to the next advance.
+#[derive(Clone, Debug)]
+pub struct Time {
+    /// Length of a fixed step
+    pub step: Duration,
+    /// Multiplies every delta, 0.5 runs at half speed
+    pub scale: f32,
+    /// Paused time doesn't advance at all
+    pub paused: bool,
+    delta: Duration,
+    elapsed: Duration,
+    accumulator: Duration,
+    steps: u32,
+}
+
+impl Time {
+    /// Time with `tps` fixed steps a second
+    pub fn new(tps: f32) -> Self {
+        Self {
+            step: Duration::from_secs_f64(1.0 / tps as f64),
+            scale: 1.0,
+            paused: false,
+            delta: Duration::ZERO,
+            elapsed: Duration::ZERO,
+            accumulator: Duration::ZERO,
+            steps: 0,
+        }
+    }
+
+    /// Adds time from a [`Clock`] each tick, stepping `tps` times a second
+    pub fn add<E: 'static>(tps: f32) -> impl FnOnce(World<E>) -> World<E> {
+        move |world| {
+            world
+                .with_resource(Self::new(tps))
+                .with_fn_system(Self::tick)
+                .in_stage(Stage::PreUpdate)
+                .after("clock")
+        }
+    }
+
+    fn tick(clock: Res<Clock>, mut time: ResMut<Time>) {
+        time.advance(clock.delta)
+    }
+
+    /// Moves time forward by `real` scaled, working out how many fixed steps
+    /// fit into it and what was left over last time
+    pub fn advance(&mut self, real: Duration) {
+        if self.paused {
+            self.delta = Duration::ZERO;
+            self.steps = 0;
+            return;
+        }
+
+        // Scaling by 1.0 through floats isn't exact, which would make the
+        // steps depend on rounding
+        self.delta = if self.scale == 1.0 {
+            real
+        } else {
+            real.mul_f64(self.scale as f64)
+        };
+        self.elapsed += self.delta;
+        self.accumulator += self.delta;
+        self.steps = (self.accumulator.as_nanos() / self.step.as_nanos()) as u32;
+        self.accumulator -= self.step * self.steps;
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false
+    }
+
+    /// Scaled time since the last advance, zero while paused
+    pub fn delta(&self) -> Duration {
+        self.delta
+    }
+
+    /// Scaled time since the start, not counting time spent paused
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// Fixed steps to run for the last advance, systems with fixed rate
+    /// logic run it this many times with a delta of `step`
+    pub fn steps(&self) -> u32 {
+        self.steps
+    }
+
+    /// How far into the next fixed step time is, from 0 to 1, for
+    /// interpolating between steps
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Clock {
     pub delta: Duration,
@@ -46,6 +154,8 @@ impl Clock {
                 last: Instant::now(),
             })
             .with_ticker(Self::tick)
+            .in_stage(Stage::PreUpdate)
+            .label("clock")
     }
 
     pub fn tick<E>(world: &World<E>) {
@@ -56,7 +166,7 @@ impl Clock {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum State {
     Stopped,
     Running,
@@ -70,3 +180,102 @@ impl Display for Name {
         write!(f, "{}", self.0)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(50);
+
+    #[test]
+    pub fn test_fixed_steps() {
+        let mut time = Time::new(20.0);
+        assert_eq!(time.step, STEP);
+
+        time.advance(Duration::from_millis(120));
+        assert_eq!(time.steps(), 2);
+        assert_eq!(time.delta(), Duration::from_millis(120));
+        assert!((time.alpha() - 0.4).abs() < 1e-4);
+
+        time.advance(Duration::from_millis(30));
+        assert_eq!(time.steps(), 1);
+        assert_eq!(time.elapsed(), Duration::from_millis(150));
+        assert_eq!(time.alpha(), 0.0);
+
+        time.advance(Duration::from_millis(10));
+        assert_eq!(time.steps(), 0);
+
+        let mut time = Time::new(20.0);
+        let steps = (0..1000)
+            .map(|_| {
+                time.advance(Duration::from_millis(1));
+                time.steps()
+            })
+            .sum::<u32>();
+        assert_eq!(steps, 20);
+        assert_eq!(time.elapsed(), Duration::from_secs(1));
+    }
+
+    #[test]
+    pub fn test_pause_scale() {
+        let mut time = Time::new(20.0);
+        time.pause();
+        time.advance(Duration::from_secs(1));
+        assert_eq!(time.steps(), 0);
+        assert_eq!(time.delta(), Duration::ZERO);
+        assert_eq!(time.elapsed(), Duration::ZERO);
+
+        time.resume();
+        time.scale = 0.5;
+        time.advance(Duration::from_millis(200));
+        assert_eq!(time.steps(), 2);
+        assert_eq!(time.delta(), Duration::from_millis(100));
+        assert_eq!(time.elapsed(), Duration::from_millis(100));
+    }
+
+    #[test]
+    pub fn test_timer() {
+        let mut time = Time::new(20.0);
+        let mut timer = Timer::new(Duration::from_millis(100));
+        assert!(timer.done(&time));
+
+        timer.start(&time);
+        time.advance(STEP);
+        assert!(!timer.done(&time));
+
+        time.pause();
+        time.advance(Duration::from_secs(10));
+        assert!(!timer.done(&time));
+
+        time.resume();
+        time.advance(Duration::from_millis(60));
+        assert!(timer.done(&time));
+
+        // Time set back to before the timer started
+        let earlier = Time::new(20.0);
+        assert!(!timer.done(&earlier));
+    }
+
+    #[test]
+    pub fn test_time_system() {
+        let world = World::<()>::new()
+            .with_resource(Clock {
+                delta: Duration::from_millis(120),
+                start: Instant::now(),
+                last: Instant::now(),
+            })
+            // Stands in for the system added by `Clock::add`, keeping the
+            // delta fixed
+            .with_ticker(|_| ())
+            .in_stage(Stage::PreUpdate)
+            .label("clock")
+            .with(Time::add(20.0));
+
+        world.tick();
+        assert_eq!(world.get::<Time>().unwrap().steps(), 2);
+        world.tick();
+        let time = world.get::<Time>().unwrap();
+        assert_eq!(time.steps(), 2);
+        assert_eq!(time.elapsed(), Duration::from_millis(240));
+    }
+}
@@ -1,77 +1,281 @@
-use std::any::TypeId;
+use std::{
+    alloc::{self, Layout},
+    any::{type_name, TypeId},
+    mem::ManuallyDrop,
+    ptr::{self, NonNull},
+};
 
+use crate::MaybeSync;
+
+/// Type erased operations, filled in the first time the concrete type is known
+#[derive(Clone, Copy)]
+struct VTable {
+    /// Layout of a single item
+    layout: Layout,
+    type_name: &'static str,
+    /// Drops `len` items starting at the pointer
+    drop: unsafe fn(*mut u8, usize),
+    /// Clones the item at the first pointer into the second
+    clone: Option<unsafe fn(*const u8, *mut u8)>,
+}
+
+impl VTable {
+    fn of<T: MaybeSync + 'static>() -> Self {
+        Self {
+            layout: Layout::new::<T>(),
+            type_name: type_name::<T>(),
+            drop: |data, len| unsafe {
+                ptr::drop_in_place(ptr::slice_from_raw_parts_mut(data.cast::<T>(), len))
+            },
+            clone: None,
+        }
+    }
+
+    fn cloneable<T: Clone + MaybeSync + 'static>() -> Self {
+        Self {
+            clone: Some(|from, to| unsafe { to.cast::<T>().write((*from.cast::<T>()).clone()) }),
+            ..Self::of::<T>()
+        }
+    }
+
+    /// An aligned pointer for vectors with nothing allocated
+    fn dangling(&self) -> NonNull<u8> {
+        NonNull::new(ptr::without_provenance_mut(self.layout.align())).unwrap()
+    }
+}
+
+/// A vector of items of a type only known at runtime. Items are moved in and
+/// out as bytes, the vtable knows how to drop and clone them.
 pub struct VecAny {
-    ptr: Option<*mut ()>,
+    data: NonNull<u8>,
     len: usize,
+    /// Number of items allocated for, `usize::MAX` for zero sized types
     cap: usize,
     ty: TypeId,
+    vtable: Option<VTable>,
 }
 
 impl VecAny {
-    pub fn new<T: 'static>() -> Self {
-        Self::from_vec::<T>(Vec::new())
+    pub fn new<T: MaybeSync + 'static>() -> Self {
+        Self::with_vtable(TypeId::of::<T>(), VTable::of::<T>())
+    }
+
+    /// Like [`VecAny::new`], but the vector can be cloned with
+    /// [`VecAny::try_clone`]
+    pub fn new_cloneable<T: Clone + MaybeSync + 'static>() -> Self {
+        Self::with_vtable(TypeId::of::<T>(), VTable::cloneable::<T>())
     }
 
     pub fn new_uninit(ty: TypeId) -> Self {
         Self {
-            ptr: None,
+            data: NonNull::dangling(),
             len: 0,
             cap: 0,
             ty,
+            vtable: None,
         }
     }
 
-    pub fn from_vec<T: 'static>(data: Vec<T>) -> Self {
-        let (ptr, len, cap) = data.into_raw_parts();
+    fn with_vtable(ty: TypeId, vtable: VTable) -> Self {
         Self {
-            ptr: Some(ptr.cast()),
-            len,
-            cap,
+            data: vtable.dangling(),
+            len: 0,
+            cap: if vtable.layout.size() == 0 {
+                usize::MAX
+            } else {
+                0
+            },
+            ty,
+            vtable: Some(vtable),
+        }
+    }
+
+    pub fn from_vec<T: MaybeSync + 'static>(data: Vec<T>) -> Self {
+        let mut data = ManuallyDrop::new(data);
+        Self {
+            // Vec allocates with the same layout and uses a dangling pointer
+            // and a capacity of usize::MAX for zero sized types too
+            data: NonNull::new(data.as_mut_ptr()).unwrap().cast(),
+            len: data.len(),
+            cap: data.capacity(),
             ty: TypeId::of::<T>(),
+            vtable: Some(VTable::of::<T>()),
         }
     }
 
-    pub fn run<T: 'static>(&mut self, f: impl FnOnce(&mut Vec<T>)) {
-        if self.ty != TypeId::of::<T>() {
+    /// Fills in the vtable of an uninitialised vector, panicking if `T` isn't
+    /// the type it holds
+    fn check<T: MaybeSync + 'static>(&mut self) {
+        assert_eq!(
+            self.ty,
+            TypeId::of::<T>(),
+            "Using a vector of {} as a vector of {}",
+            self.vtable
+                .map_or("another type", |vtable| vtable.type_name),
+            type_name::<T>()
+        );
+        if self.vtable.is_none() {
+            *self = Self::new::<T>();
+        }
+    }
+
+    /// Pointer to the item at `index`, which may be one past the end
+    fn ptr(&self, index: usize) -> *mut u8 {
+        let size = self.vtable.map_or(0, |vtable| vtable.layout.size());
+        // SAFETY: indices are at most `cap`, so the offset stays within the
+        // allocation or is zero for zero sized types
+        unsafe { self.data.as_ptr().add(index * size) }
+    }
+
+    fn layout(vtable: &VTable, cap: usize) -> Layout {
+        Layout::from_size_align(
+            vtable
+                .layout
+                .size()
+                .checked_mul(cap)
+                .expect("Capacity overflow"),
+            vtable.layout.align(),
+        )
+        .expect("Capacity overflow")
+    }
+
+    /// Makes room for at least one more item
+    fn reserve(&mut self) {
+        if self.len < self.cap {
             return;
         }
+        let vtable = self.vtable.expect("Reserving in an uninitialised vector");
 
-        let mut data: Vec<T> = match self.ptr {
-            Some(ptr) => unsafe { Vec::<T>::from_raw_parts(ptr.cast(), self.len, self.cap) },
-            None => Vec::<T>::new(),
+        let cap = (self.cap * 2).max(4);
+        let layout = Self::layout(&vtable, cap);
+        // SAFETY: the layout isn't zero sized as zero sized types never run
+        // out of capacity, and the old allocation used the old capacity
+        let data = unsafe {
+            if self.cap == 0 {
+                alloc::alloc(layout)
+            } else {
+                alloc::realloc(
+                    self.data.as_ptr(),
+                    Self::layout(&vtable, self.cap),
+                    layout.size(),
+                )
+            }
         };
-        f(&mut data);
-
-        let (ptr, len, cap) = data.into_raw_parts();
-        self.ptr = Some(ptr.cast());
-        self.len = len;
+        self.data = NonNull::new(data).unwrap_or_else(|| alloc::handle_alloc_error(layout));
         self.cap = cap;
     }
 
+    /// Creates an empty vector of the same type, only possible once the type
+    /// has been used to construct or modify this vector
+    pub fn new_like(&self) -> Option<Self> {
+        self.vtable.map(|vtable| Self::with_vtable(self.ty, vtable))
+    }
+
+    /// Clones every item, `None` unless the vector was created with
+    /// [`VecAny::new_cloneable`]
+    pub fn try_clone(&self) -> Option<Self> {
+        let clone = self.vtable?.clone?;
+        let mut other = self.new_like()?;
+        for index in 0..self.len {
+            other.reserve();
+            // SAFETY: index is in bounds and there is room for another item,
+            // len is only bumped once it is written so a panicking clone
+            // doesn't leave an uninitialised item to drop
+            unsafe { clone(self.ptr(index), other.ptr(index)) };
+            other.len += 1;
+        }
+        Some(other)
+    }
+
+    /// Drops the item at `index`, moving the last item into its place
+    pub fn swap_remove(&mut self, index: usize) {
+        assert!(index < self.len, "Index {index} out of bounds");
+        let Some(vtable) = self.vtable else {
+            return;
+        };
+
+        let last = self.len - 1;
+        // Shrink first so a panicking drop can't lead to a double drop
+        self.len = last;
+        // SAFETY: both indices are in bounds, and the removed item is moved
+        // past the end before being dropped
+        unsafe {
+            if index != last {
+                ptr::swap_nonoverlapping(self.ptr(index), self.ptr(last), vtable.layout.size());
+            }
+            (vtable.drop)(self.ptr(last), 1);
+        }
+    }
+
+    /// Moves the item at index onto the end of other, which must hold the same type
+    pub fn swap_remove_into(&mut self, index: usize, other: &mut VecAny) {
+        assert_eq!(
+            self.ty, other.ty,
+            "Moving between vectors of different types"
+        );
+        assert!(index < self.len, "Index {index} out of bounds");
+        let Some(vtable) = self.vtable else {
+            return;
+        };
+
+        if other.vtable.is_none() {
+            *other = Self::with_vtable(self.ty, vtable);
+        }
+        other.reserve();
+        let last = self.len - 1;
+        // SAFETY: both indices are in bounds and other has room for the item,
+        // which is owned by other once it has been copied
+        unsafe {
+            ptr::copy_nonoverlapping(self.ptr(index), other.ptr(other.len), vtable.layout.size());
+            other.len += 1;
+            if index != last {
+                ptr::copy_nonoverlapping(self.ptr(last), self.ptr(index), vtable.layout.size());
+            }
+        }
+        self.len = last;
+    }
+
+    pub fn pop<T: MaybeSync + 'static>(&mut self) -> Option<T> {
+        self.check::<T>();
+        if self.len == 0 {
+            return None;
+        }
+        self.len -= 1;
+        // SAFETY: the item was in bounds and is no longer owned by the vector
+        Some(unsafe { self.ptr(self.len).cast::<T>().read() })
+    }
+
     pub fn downcast_ref<T: 'static>(&self) -> Option<&[T]> {
         if self.ty != TypeId::of::<T>() {
             return None;
         }
+        if self.vtable.is_none() {
+            return Some(&[]);
+        }
 
-        Some(match self.ptr {
-            Some(ptr) => unsafe { std::slice::from_raw_parts(ptr.cast(), self.len) },
-            None => &[],
-        })
+        // SAFETY: the type matches and the pointer is aligned even when
+        // nothing is allocated
+        Some(unsafe { std::slice::from_raw_parts(self.data.as_ptr().cast(), self.len) })
     }
 
     pub fn downcast_mut<T: 'static>(&mut self) -> Option<&mut [T]> {
         if self.ty != TypeId::of::<T>() {
             return None;
         }
+        if self.vtable.is_none() {
+            return Some(&mut []);
+        }
 
-        Some(match self.ptr {
-            Some(ptr) => unsafe { std::slice::from_raw_parts_mut(ptr.cast(), self.len) },
-            None => &mut [],
-        })
+        // SAFETY: see downcast_ref
+        Some(unsafe { std::slice::from_raw_parts_mut(self.data.as_ptr().cast(), self.len) })
     }
 
-    pub fn push<T: 'static>(&mut self, item: T) {
-        self.run(|data| data.push(item))
+    pub fn push<T: MaybeSync + 'static>(&mut self, item: T) {
+        self.check::<T>();
+        self.reserve();
+        // SAFETY: there is room for the item and the type matches
+        unsafe { self.ptr(self.len).cast::<T>().write(item) };
+        self.len += 1;
     }
 
     pub fn len(&self) -> usize {
@@ -87,9 +291,82 @@ impl VecAny {
     }
 }
 
+// SAFETY: every way of putting a T in requires T: MaybeSync, which is
+// Send + Sync with the parallel feature
+#[cfg(feature = "parallel")]
+unsafe impl Send for VecAny {}
+#[cfg(feature = "parallel")]
+unsafe impl Sync for VecAny {}
+
+impl Drop for VecAny {
+    fn drop(&mut self) {
+        let Some(vtable) = self.vtable else {
+            return;
+        };
+
+        /// Frees the allocation even if dropping an item panics
+        struct Dealloc<'a>(&'a VecAny, VTable);
+
+        impl Drop for Dealloc<'_> {
+            fn drop(&mut self) {
+                let Self(vec, vtable) = self;
+                if vtable.layout.size() != 0 && vec.cap != 0 {
+                    // SAFETY: allocated in reserve or by Vec with this layout
+                    unsafe { alloc::dealloc(vec.data.as_ptr(), VecAny::layout(vtable, vec.cap)) }
+                }
+            }
+        }
+
+        let len = std::mem::take(&mut self.len);
+        let _dealloc = Dealloc(self, vtable);
+        // SAFETY: the first len items are initialised and owned by the vector
+        unsafe { (vtable.drop)(self.data.as_ptr(), len) }
+    }
+}
+
+// These should also pass under `cargo +nightly miri test --lib vecany`
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::{
+        panic::{catch_unwind, AssertUnwindSafe},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    /// Counts how many times it has been dropped
+    struct Counted<'a>(&'a AtomicUsize, String);
+
+    impl Drop for Counted<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    impl Clone for Counted<'_> {
+        fn clone(&self) -> Self {
+            if self.1 == "panic" {
+                panic!("Clone panicked");
+            }
+            Self(self.0, self.1.clone())
+        }
+    }
+
+    fn counted(drops: &'static AtomicUsize, items: &[&str]) -> VecAny {
+        let mut vecany = VecAny::new_cloneable::<Counted<'static>>();
+        items
+            .iter()
+            .for_each(|item| vecany.push(Counted(drops, item.to_string())));
+        vecany
+    }
+
+    fn names(vecany: &VecAny) -> Vec<&str> {
+        vecany
+            .downcast_ref::<Counted<'static>>()
+            .unwrap()
+            .iter()
+            .map(|item| item.1.as_str())
+            .collect()
+    }
 
     #[test]
     pub fn test_normal() {
@@ -105,6 +382,8 @@ mod tests {
     #[test]
     pub fn test_uninit() {
         let mut vecany = VecAny::new_uninit(TypeId::of::<usize>());
+        assert_eq!(vecany.downcast_ref::<usize>(), Some([].as_slice()));
+        assert!(vecany.new_like().is_none());
         vecany.push(0_usize);
         vecany.push(1_usize);
         vecany.push(2_usize);
@@ -112,4 +391,116 @@ mod tests {
         assert_eq!(Some(data.as_slice()), vecany.downcast_ref::<usize>());
         assert_eq!(Some(data.as_mut_slice()), vecany.downcast_mut::<usize>());
     }
+
+    #[test]
+    pub fn test_swap_remove_into() {
+        let mut a = VecAny::new::<String>();
+        a.push(String::from("a"));
+        a.push(String::from("b"));
+        a.push(String::from("c"));
+        let mut b = a.new_like().unwrap();
+
+        a.swap_remove_into(0, &mut b);
+        a.swap_remove(0);
+        assert_eq!(Some([String::from("b")].as_slice()), a.downcast_ref());
+        assert_eq!(Some([String::from("a")].as_slice()), b.downcast_ref());
+        assert_eq!(Some(String::from("a")), b.pop());
+        assert!(b.is_empty());
+    }
+
+    #[test]
+    pub fn test_drops() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let mut a = counted(&DROPS, &["a", "b", "c", "d", "e"]);
+        let mut b = a.new_like().unwrap();
+
+        a.swap_remove(1);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        assert_eq!(names(&a), vec!["a", "e", "c", "d"]);
+
+        a.swap_remove_into(0, &mut b);
+        a.swap_remove_into(2, &mut b);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        assert_eq!(names(&a), vec!["d", "e"]);
+        assert_eq!(names(&b), vec!["a", "c"]);
+
+        let popped = b.pop::<Counted>().unwrap();
+        assert_eq!(popped.1, "c");
+        drop(popped);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+
+        let c = a.try_clone().unwrap();
+        assert_eq!(names(&c), vec!["d", "e"]);
+        drop((a, b, c));
+        assert_eq!(DROPS.load(Ordering::SeqCst), 7);
+    }
+
+    #[test]
+    pub fn test_clone_panic() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let a = counted(&DROPS, &["a", "panic", "c"]);
+        assert!(catch_unwind(AssertUnwindSafe(|| a.try_clone())).is_err());
+        // Only the clone of "a" had been made
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+        drop(a);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 4);
+        assert!(VecAny::new::<u8>().try_clone().is_none());
+    }
+
+    #[test]
+    pub fn test_wrong_type() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        let mut a = counted(&DROPS, &["a"]);
+        assert!(catch_unwind(AssertUnwindSafe(|| a.push(0_u32))).is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| a.pop::<u32>())).is_err());
+        assert_eq!(a.downcast_ref::<u32>(), None);
+        assert_eq!(names(&a), vec!["a"]);
+        drop(a);
+        assert_eq!(DROPS.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    pub fn test_from_vec() {
+        let mut a = VecAny::from_vec(vec![String::from("a"), String::from("b")]);
+        a.push(String::from("c"));
+        a.swap_remove(0);
+        assert_eq!(
+            a.downcast_ref::<String>(),
+            Some([String::from("c"), String::from("b")].as_slice())
+        );
+    }
+
+    #[test]
+    pub fn test_zero_sized() {
+        #[derive(Clone, Debug, PartialEq)]
+        struct Marker;
+
+        let mut a = VecAny::new_cloneable::<Marker>();
+        (0..100).for_each(|_| a.push(Marker));
+        let mut b = a.new_like().unwrap();
+        a.swap_remove(3);
+        a.swap_remove_into(0, &mut b);
+        assert_eq!(a.len(), 98);
+        assert_eq!(b.downcast_ref::<Marker>(), Some([Marker].as_slice()));
+        assert_eq!(a.try_clone().unwrap().len(), 98);
+        assert_eq!(a.pop(), Some(Marker));
+
+        let mut c = VecAny::from_vec(vec![(); 3]);
+        c.push(());
+        assert_eq!(c.downcast_mut::<()>().map(|c| c.len()), Some(4));
+    }
+
+    #[test]
+    pub fn test_over_aligned() {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        #[repr(align(64))]
+        struct Aligned(u8);
+
+        let mut a = VecAny::new::<Aligned>();
+        (0..10).for_each(|i| a.push(Aligned(i)));
+        a.swap_remove(0);
+        let items = a.downcast_ref::<Aligned>().unwrap();
+        assert_eq!(items.as_ptr() as usize % 64, 0);
+        assert_eq!(items[0], Aligned(9));
+    }
 }
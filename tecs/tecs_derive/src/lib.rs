@@ -1,53 +1,253 @@
 use proc_macro::TokenStream;
-use quote::quote;
-use syn::{parse_macro_input, Data, DataStruct, DeriveInput, Fields, FieldsNamed};
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parse_macro_input, parse_quote, spanned::Spanned, Data, DeriveInput, Field, Generics, LitInt,
+    LitStr, Member, Type,
+};
 
-#[proc_macro_derive(Archetype)]
-pub fn derive_answer_fn(input: TokenStream) -> TokenStream {
-    let input = parse_macro_input!(input as DeriveInput);
-    let Data::Struct(DataStruct {
-        fields: Fields::Named(FieldsNamed { named, .. }), 
-        ..
-    }) = input.data
-    else {
-        panic!()
+/// Options from `#[archetype(name = "...", version = 1)]`, the name saved in
+/// scenes defaults to the struct name and the schema version to 0
+fn archetype_options(input: &DeriveInput) -> syn::Result<(String, u32)> {
+    let mut name = input.ident.to_string();
+    let mut version = 0;
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("archetype")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else if meta.path.is_ident("version") {
+                version = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("Expected `name = \"...\"` or `version = ...`"))
+            }
+        })?;
+    }
+    Ok((name, version))
+}
+
+/// How a field of an archetype or bundle is stored
+enum Storage {
+    Column,
+    /// `#[archetype(skip)]`, not stored and filled with its default when the
+    /// archetype is read back
+    Skip,
+    /// `#[archetype(flatten)]`, a [`Bundle`] whose fields are stored as
+    /// columns of their own
+    Flatten,
+}
+
+fn storage(field: &Field) -> syn::Result<Storage> {
+    let mut storage = Storage::Column;
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("archetype")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("skip") {
+                storage = Storage::Skip;
+                Ok(())
+            } else if meta.path.is_ident("flatten") {
+                storage = Storage::Flatten;
+                Ok(())
+            } else {
+                Err(meta.error("Expected `skip` or `flatten`"))
+            }
+        })?;
+    }
+    Ok(storage)
+}
+
+/// The parts of an archetype or bundle impl that store its fields
+struct Expanded {
+    generics: Generics,
+    /// Expression for the columns the fields are stored in
+    columns: TokenStream2,
+    /// Expression for the reflected components, in the same order as `columns`
+    components: TokenStream2,
+    /// Statements pushing the fields of `self` onto `table`
+    push: TokenStream2,
+    /// Field initializers reading the fields from `row` of `table`
+    get: TokenStream2,
+}
+
+fn expand_fields(input: &DeriveInput, derive: &str) -> syn::Result<Expanded> {
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            format!("{derive} can only be derived for structs"),
+        ));
     };
 
-    let ident = input.ident;
-    let fields = &named.iter().map(|field| field.ident.as_ref().unwrap()).collect::<Vec<_>>();
-    let types = &named.iter().map(|field| &field.ty).collect::<Vec<_>>();
+    let mut generics = input.generics.clone();
+    let predicates = &mut generics.make_where_clause().predicates;
+    predicates.push(parse_quote!(Self: 'static));
+
+    let mut columns: Vec<(Member, &Type)> = Vec::new();
+    let mut skips = Vec::new();
+    let mut bundles: Vec<(Member, &Type)> = Vec::new();
+    for (index, field) in data.fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::Unnamed(index.into()),
+        };
+        let ty = &field.ty;
+        match storage(field)? {
+            Storage::Column => (),
+            Storage::Skip => {
+                predicates.push(parse_quote!(#ty: Default));
+                skips.push(member);
+                continue;
+            }
+            Storage::Flatten => {
+                predicates.push(parse_quote!(#ty: tecs::Bundle));
+                bundles.push((member, ty));
+                continue;
+            }
+        }
+
+        // Columns are found by type, so a second column of the same type
+        // would never be read
+        let name = ty.to_token_stream().to_string();
+        if let Some((other, _)) = columns
+            .iter()
+            .find(|(_, other)| other.to_token_stream().to_string() == name)
+        {
+            return Err(syn::Error::new(
+                ty.span(),
+                format!(
+                    "`{}` has the same type as `{}`, archetypes can only have one component of each type",
+                    member.to_token_stream(),
+                    other.to_token_stream()
+                ),
+            ));
+        }
+        predicates.push(parse_quote!(#ty: tecs::MaybeSync + Clone + 'static));
+        columns.push((member, ty));
+    }
+
+    let (fields, types): (Vec<_>, Vec<_>) = columns.into_iter().unzip();
+    let (bundles, bundle_types): (Vec<_>, Vec<_>) = bundles.into_iter().unzip();
+    let names = fields
+        .iter()
+        .map(|field| field.to_token_stream().to_string());
+    Ok(Expanded {
+        generics,
+        columns: if bundle_types.is_empty() {
+            quote!(vec![#(tecs::Column::new::<#types>()),*])
+        } else {
+            quote! {{
+                let mut columns = vec![#(tecs::Column::new::<#types>()),*];
+                #(columns.extend(<#bundle_types as tecs::Bundle>::columns());)*
+                columns
+            }}
+        },
+        components: quote! {{
+            use tecs::reflect::{ReflectOpaque as _, ReflectSerialize as _};
+            let mut components = vec![#((&&tecs::reflect::Reflect::<#types>::new()).component(#names)),*];
+            #(components.extend(<#bundle_types as tecs::Bundle>::components());)*
+            components
+        }},
+        push: quote! {
+            #(table.push::<#types>(self.#fields, tick);)*
+            #(tecs::Bundle::push(self.#bundles, table, tick);)*
+        },
+        get: quote! {
+            #(#fields: table.column::<#types>().unwrap()[row.0 as usize].clone(),)*
+            #(#bundles: <#bundle_types as tecs::Bundle>::get(table, row),)*
+            #(#skips: Default::default(),)*
+        },
+    })
+}
+
+#[proc_macro_derive(Archetype, attributes(archetype))]
+pub fn derive_archetype(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_archetype(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand_archetype(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let (name, version) = archetype_options(&input)?;
+    let Expanded {
+        generics,
+        columns,
+        components,
+        push,
+        get,
+    } = expand_fields(&input, "Archetype")?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics tecs::Archetype for #ident #ty_generics #where_clause {
+            fn name() -> &'static str {
+                #name
+            }
+
+            fn version() -> u32 {
+                #version
+            }
 
-    let expanded = quote! {
-        impl tecs::Archetype for #ident {
-            fn columns() -> Vec<std::any::TypeId> {
-                vec![#(std::any::TypeId::of::<#types>()),*]
+            fn columns() -> Vec<tecs::Column> {
+                #columns
             }
 
-            fn add(self, table: &tecs::Table) -> tecs::RowIndex {
+            fn components() -> Vec<tecs::reflect::Component> {
+                #components
+            }
+
+            fn add(self, table: &tecs::Table, id: tecs::EntityId, tick: u32) -> tecs::RowIndex {
                 table.length.set(table.length.get() + 1);
-                let mut columns = table.columns_mut();
-                #(
-                    columns.next().unwrap().data.push::<#types>(self.#fields);
-                )*
+                table.entities.borrow_mut().push(id);
+                #push
                 tecs::RowIndex(table.length.get() as u32 - 1)
             }
 
-            fn remove(table: &tecs::Table, row: tecs::RowIndex) {
-                table.length.set(table.length.get() - 1);
-                let mut columns = table.columns_mut();
-                #(
-                    columns.next().unwrap().data.run::<#types>(|data| { data.swap_remove(row.0 as usize); });
-                )*
+            fn get(table: &tecs::Table, row: tecs::RowIndex) -> Self {
+                Self { #get }
+            }
+        }
+    })
+}
+
+/// Bundles take the same field options as archetypes, so bundles can be
+/// nested with `#[archetype(flatten)]`
+#[proc_macro_derive(Bundle, attributes(archetype))]
+pub fn derive_bundle(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand_bundle(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn expand_bundle(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let Expanded {
+        generics,
+        columns,
+        components,
+        push,
+        get,
+    } = expand_fields(&input, "Bundle")?;
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics tecs::Bundle for #ident #ty_generics #where_clause {
+            fn columns() -> Vec<tecs::Column> {
+                #columns
+            }
+
+            fn components() -> Vec<tecs::reflect::Component> {
+                #components
+            }
+
+            fn push(self, table: &tecs::Table, tick: u32) {
+                #push
             }
 
             fn get(table: &tecs::Table, row: tecs::RowIndex) -> Self {
-                let mut columns = table.columns();
-                Self {
-                    #(#fields: columns.next().unwrap().get::<#types>(row).unwrap().clone()),*
-                }
+                Self { #get }
             }
         }
-    };
-
-    TokenStream::from(expanded)
+    })
 }
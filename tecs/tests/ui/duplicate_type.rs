@@ -0,0 +1,9 @@
+use tecs::prelude::*;
+
+#[derive(Archetype, Clone)]
+struct Thing {
+    health: f32,
+    speed: f32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use tecs::prelude::*;
+
+#[derive(Archetype)]
+enum Thing {
+    A(u32),
+    B(f32),
+}
+
+fn main() {}
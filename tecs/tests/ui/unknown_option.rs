@@ -0,0 +1,15 @@
+use tecs::prelude::*;
+
+#[derive(Archetype, Clone)]
+#[archetype(label = "thing")]
+struct Thing {
+    value: u32,
+}
+
+#[derive(Archetype, Clone)]
+struct Other {
+    #[archetype(ignore)]
+    value: u32,
+}
+
+fn main() {}
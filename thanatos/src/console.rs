@@ -0,0 +1,86 @@
+use std::{
+    io::BufRead,
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
+};
+
+use crate::World;
+
+/// Debug commands typed into the terminal the game was started from
+pub struct Console {
+    lines: Mutex<Receiver<String>>,
+}
+
+impl Console {
+    fn new() -> Self {
+        let (sender, lines) = channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            lines: Mutex::new(lines),
+        }
+    }
+}
+
+fn run(world: &World, command: &str) {
+    let mut args = command.split_whitespace();
+    match args.next() {
+        Some("entities") => world
+            .entity_list()
+            .into_iter()
+            .for_each(|(entity, archetype)| {
+                println!("{} {archetype}", entity.index());
+            }),
+        Some("archetypes") => world.archetype_infos().into_iter().for_each(|info| {
+            let components = info
+                .components
+                .iter()
+                .map(|component| format!("{}: {}", component.name, component.type_name))
+                .collect::<Vec<_>>();
+            println!(
+                "{} v{} ({} entities) {}",
+                info.name,
+                info.version,
+                info.entities,
+                components.join(", ")
+            );
+        }),
+        Some("dump") => {
+            let Some(index) = args.next().and_then(|index| index.parse::<u32>().ok()) else {
+                println!("Usage: dump <entity>");
+                return;
+            };
+            let dump = world
+                .entity_list()
+                .into_iter()
+                .find(|(entity, _)| entity.index() == index)
+                .and_then(|(entity, _)| world.dump_entity(entity));
+            match dump {
+                Some(dump) => println!("{}", serde_json::to_string_pretty(&dump).unwrap()),
+                None => println!("No entity {index}"),
+            }
+        }
+        Some(other) => println!("Unknown command {other}, expected entities, archetypes or dump"),
+        None => (),
+    }
+}
+
+pub fn tick(world: &World) {
+    let commands = {
+        let console = world.get::<Console>().unwrap();
+        let lines = console.lines.lock().unwrap();
+        lines.try_iter().collect::<Vec<_>>()
+    };
+    commands.iter().for_each(|command| run(world, command));
+}
+
+pub fn add(world: World) -> World {
+    world.with_resource(Console::new()).with_ticker(tick)
+}
@@ -5,7 +5,7 @@ use tecs::{EntityId, Is};
 
 use crate::{
     collider::Collider, interact::Interactable, net::Connection, player::Player, renderer::Ui,
-    transform::Transform, Timer, World,
+    transform::Transform, Time, Timer, World,
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -20,31 +20,33 @@ impl Gatherable {
         self.collider.within(position)
     }
 
-    pub fn gather(&mut self) -> usize {
-        self.timer.start();
+    pub fn gather(&mut self, time: &Time) -> usize {
+        self.timer.start(time);
         self.loot
     }
 }
 
 pub fn tick(world: &World) {
+    let time = world.get::<Time>().unwrap();
     let entity = {
         let (gatherables, mut interactables, entities) =
             world.query::<(&Gatherable, &mut Interactable, EntityId)>();
 
-        interactables.for_each(|interactable| interactable.priority = f32::MAX);
+        interactables.for_each(|mut interactable| interactable.priority = f32::MAX);
 
-        let (transforms, _) = world.query::<(&Transform, Is<Player>)>();
-        let transform = transforms.iter().next().unwrap();
+        let Ok((transform, _)) = world.try_query_one::<(&Transform, Is<Player>)>() else {
+            return;
+        };
         let Some((_, entity)) = gatherables
             .iter()
-            .zip(entities)
-            .filter(|(gatherable, _)| gatherable.timer.done())
+            .zip(entities.iter())
+            .filter(|(gatherable, _)| gatherable.timer.done(&time))
             .filter(|(gatherable, _)| gatherable.gatherable(transform.translation))
             .next()
         else {
             return;
         };
-        entity
+        *entity
     };
 
     let mut interactable = world.get_component_mut::<Interactable>(entity).unwrap();
@@ -54,7 +56,7 @@ pub fn tick(world: &World) {
     if interactable.signal.map(|signal| ui.signals.get(signal)).unwrap_or_default() {
         let mut gatherable = world.get_component_mut::<Gatherable>(entity).unwrap();
         let mut conn = world.get_mut::<Connection>().unwrap();
-        conn.write(Serverbound::Gather(gatherable.gather()))
+        conn.write(Serverbound::Gather(gatherable.gather(&time)))
             .unwrap();
     }
 }
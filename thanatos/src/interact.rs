@@ -41,7 +41,7 @@ fn interact_ui(world: &World) {
     else {
         return;
     };
-    let interactable = interactables.get_mut(index).unwrap();
+    let mut interactable = interactables.get_mut(index).unwrap();
     if interactable.priority == f32::MAX {
         return;
     }
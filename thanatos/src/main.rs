@@ -2,6 +2,7 @@ mod assets;
 mod camera;
 mod collider;
 mod colours;
+mod console;
 mod craft;
 mod equipment;
 mod event;
@@ -16,61 +17,31 @@ mod window;
 
 use crate::{camera::Camera, window::Window};
 use anyhow::Result;
-use assets::{Material, MeshCache, MeshId};
-use collider::{Collider, ColliderKind};
+use assets::MeshCache;
 use event::Event;
 use gather::Gatherable;
-use glam::{Vec3, Vec4};
+use glam::Vec3;
 use interact::Interactable;
 use net::Connection;
-use nyx::task::Proficiencies;
+use nyx::{protocol::TPS, task::Proficiencies};
 use player::Player;
-use renderer::{RenderObject, Renderer};
+use renderer::{Renderable, Renderer};
 use serde::{Deserialize, Serialize};
-use std::time::Duration;
 use tecs::prelude::*;
-use tecs::scene::Scene;
-use tecs::utils::{Clock, Name, State, Timer};
+use tecs::scene::{Prefab, Scene};
+use tecs::utils::{Clock, Name, State, Time, Timer};
 use transform::Transform;
 
 #[derive(Archetype, Clone, Serialize, Deserialize)]
 struct CopperOre {
-    pub render: RenderObject,
-    pub transform: Transform,
+    #[archetype(flatten)]
+    #[serde(flatten)]
+    pub renderable: Renderable,
     pub gatherable: Gatherable,
     pub interactable: Interactable,
     pub name: Name,
 }
 
-impl CopperOre {
-    pub fn new(world: &World) -> Result<Self> {
-        Ok(CopperOre {
-            render: RenderObject {
-                mesh: MeshId(String::from("assets/meshes/copper_ore.glb")),
-                material: Material {
-                    colour: Vec4::new(1.0, 0.5, 0.0, 1.0),
-                },
-            },
-            transform: Transform::IDENTITY,
-            gatherable: Gatherable {
-                collider: Collider {
-                    kind: ColliderKind::Sphere(5.0),
-                    position: Vec3::ZERO,
-                },
-                loot: 0,
-                timer: Timer::new(Duration::from_secs(1)),
-            },
-            interactable: Interactable::new(&world, "Gather Copper Ore"),
-            name: Name(String::from("Copper Ore")),
-        })
-    }
-
-    pub fn with_transform(mut self, transform: Transform) -> Self {
-        self.transform = transform;
-        self
-    }
-}
-
 pub type World = tecs::World<Event>;
 
 fn main() -> Result<()> {
@@ -84,6 +55,10 @@ fn main() -> Result<()> {
     let world = World::new()
         .register::<Player>()
         .register::<CopperOre>()
+        .with_prefab(
+            "copper_ore",
+            Prefab::load(&std::fs::read("assets/prefabs/copper_ore.prefab")?)?,
+        )
         .with_resource(State::Running)
         .with_resource(Proficiencies::default())
         .with_resource(MeshCache::default())
@@ -92,10 +67,13 @@ fn main() -> Result<()> {
         .with(renderer.add())
         .with(camera.add())
         .with(Clock::add)
+        .with(Time::add(TPS))
         .with(inventory::add)
         .with(craft::add)
         .with(equipment::add)
         .with(interact::add)
+        .with(transform::add)
+        .with(console::add)
         .with_handler(|world, event| match event {
             Event::Stop => {
                 *world.get_mut::<State>().unwrap() = State::Stopped;
@@ -106,8 +84,10 @@ fn main() -> Result<()> {
             let clock = world.get::<Clock>().unwrap();
             println!("FPS: {}", 1.0 / clock.delta.as_secs_f32());
         })
-        .with_ticker(Player::tick)
+        .with_fn_system(Player::tick)
+        .run_if(resource_equals(State::Running))
         .with_ticker(gather::tick)
+        .run_if(resource_equals(State::Running))
         .with(net::add);
 
     let mut transform = Transform::IDENTITY;
@@ -115,31 +95,36 @@ fn main() -> Result<()> {
 
     /*
     world.spawn(Player {
-        render: RenderObject {
-            mesh: MeshId(String::from("assets/meshes/cube.glb")),
-            material: Material { colour: Vec4::ONE },
+        renderable: Renderable {
+            render: RenderObject {
+                mesh: MeshId(String::from("assets/meshes/cube.glb")),
+                material: Material { colour: Vec4::ONE },
+            },
+            transform,
         },
-        transform,
         health: Health(100.0),
     });
 
-    world.spawn(CopperOre::new(&world)?.with_transform(Transform {
-        translation: Vec3::ONE,
-        rotation: Quat::IDENTITY,
-        scale: Vec3::new(3.0, 1.0, 2.0),
-    }));
+    world.spawn_prefab(
+        "copper_ore",
+        serde_json::json!({
+            "transform": Transform {
+                translation: Vec3::ONE,
+                rotation: Quat::IDENTITY,
+                scale: Vec3::new(3.0, 1.0, 2.0),
+            }
+        }),
+    )?;
 
     let mut scene = Scene::default();
     scene.from_world(&world);
 
-    let mut buffer: Vec<u8> = Vec::new();
-    let mut serializer = serde_json::Serializer::pretty(&mut buffer);
-    scene.save(&world, &mut serializer).unwrap();
+    let buffer = scene.save_bytes(&world, tecs::scene::SceneFormat::Json).unwrap();
     std::fs::write("assets/scenes/test.scene", buffer).unwrap();
     */
 
     let buffer = std::fs::read("assets/scenes/test.scene").unwrap();
-    Scene::load(&world, &mut serde_json::Deserializer::from_slice(&buffer)).unwrap();
+    Scene::load_bytes(&world, &buffer).unwrap();
 
     loop {
         if let State::Stopped = *world.get::<State>().unwrap() {
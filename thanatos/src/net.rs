@@ -2,7 +2,7 @@ use anyhow::Result;
 use glam::{Vec3, Vec4};
 use nyx::protocol::{ClientId, Clientbound, ClientboundBundle, Serverbound, Tick, TPS};
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell},
     collections::{HashMap, VecDeque},
     io::ErrorKind,
     net::UdpSocket,
@@ -14,7 +14,7 @@ use crate::{
     assets::{Material, MeshId},
     event::Event,
     player::Player,
-    renderer::RenderObject,
+    renderer::{RenderObject, Renderable},
     transform::Transform,
     World,
 };
@@ -83,6 +83,8 @@ impl Connection {
         world
             .with_resource(Self::new().unwrap())
             .with_ticker(Self::tick)
+            .in_stage(Stage::PreUpdate)
+            .label("net")
     }
 }
 
@@ -128,16 +130,27 @@ impl Positions {
 #[derive(Archetype, Clone)]
 pub struct OtherPlayer {
     pub client_id: ClientId,
-    pub render: RenderObject,
-    pub transform: Transform,
+    #[archetype(flatten)]
+    pub renderable: Renderable,
     pub positions: Positions,
 }
 
 pub struct MovementSystem {
     positions: RefCell<HashMap<Tick, Vec3>>,
+    /// Whether the player has moved since their position was last sent
+    moved: Cell<bool>,
 }
 
 impl MovementSystem {
+    /// Remembers if the player moved since this system last ran, checked
+    /// whenever it runs so changes between events aren't missed
+    fn track(&self, world: &World) {
+        let (changed, _) = world.query::<(Changed<Transform>, Is<Player>)>();
+        if changed.iter().any(|changed| changed) {
+            self.moved.set(true);
+        }
+    }
+
     fn spawn(&self, world: &World, client_id: ClientId, position: Vec3) {
         let render = RenderObject {
             mesh: MeshId(String::from("assets/meshes/cube.glb")),
@@ -147,14 +160,15 @@ impl MovementSystem {
         transform.translation = position;
         world.spawn(OtherPlayer {
             client_id,
-            render,
-            transform,
+            renderable: Renderable { render, transform },
             positions: Positions::new(),
         });
     }
 
     fn move_player(&self, world: &World, position: Vec3, tick: Tick) {
-        let (mut transform, _) = world.query_one::<(&mut Transform, Is<Player>)>();
+        let Ok((mut transform, _)) = world.try_query_one::<(&mut Transform, Is<Player>)>() else {
+            return;
+        };
 
         if let Some(actual) = self.positions.borrow().get(&tick) {
             if position == *actual {
@@ -166,61 +180,52 @@ impl MovementSystem {
     }
 
     fn move_other_player(&self, world: &World, client_id: ClientId, position: Vec3) {
-        let (mut positions, client_ids, _) =
-            world.query::<(&mut Positions, &ClientId, Is<OtherPlayer>)>();
-        let mut n = client_ids
-            .iter()
-            .position(|other| client_id == *other)
-            .unwrap() as i64;
-
-        positions.for_each(|positions| {
-            if n == 0 {
-                positions.push(position);
-            };
-            n -= 1
-        })
+        let mut query = world.query::<(&mut Positions, &ClientId, Is<OtherPlayer>)>();
+        query
+            .iter_mut()
+            .filter(|(_, other, _)| **other == client_id)
+            .for_each(|(mut positions, _, _)| positions.push(position));
     }
 
     fn update_buffered_positions(world: &World) {
-        let (mut transforms, mut positions) = world.query::<(&mut Transform, &mut Positions)>();
-        let mut positions = positions.map(|position| position.get()).into_iter();
-        transforms.for_each(|transform| {
-            if let Some(position) = positions.next().unwrap() {
+        let mut query = world.query::<(&mut Transform, &mut Positions)>();
+        query.iter_mut().for_each(|(mut transform, mut positions)| {
+            if let Some(position) = positions.get() {
                 transform.translation = position
             }
         });
     }
 
     fn despawn(&self, world: &World, client_id: ClientId) {
-        let id = {
-            let (entities, client_ids, _) = world.query::<(EntityId, &ClientId, Is<OtherPlayer>)>();
-            entities
-                .iter()
-                .zip(client_ids.iter().collect::<Vec<_>>())
-                .find(|(_, id)| **id == client_id)
-                .map(|(entity, _)| *entity)
-        };
-
-        if let Some(id) = id {
-            world.despawn::<OtherPlayer>(id);
-        }
+        let mut query = world.query::<(EntityId, &ClientId, Is<OtherPlayer>)>();
+        query
+            .iter_mut()
+            .filter(|(_, id, _)| **id == client_id)
+            .for_each(|(entity, _, _)| world.commands().despawn(*entity));
     }
 
     fn send_player_position(&self, world: &World) {
+        if !self.moved.get() {
+            return;
+        }
+        let Ok((transform, _)) = world.try_query_one::<(&Transform, Is<Player>)>() else {
+            return;
+        };
+        let position = transform.translation;
         let mut conn = world.get_mut::<Connection>().unwrap();
-        let (transforms, _) = world.query::<(&Transform, Is<Player>)>();
-        let position = transforms.iter().next().unwrap().translation;
         if conn.id.is_none() {
             return;
         }
         let tick = conn.tick;
         conn.write(Serverbound::Move(position, tick)).unwrap();
         self.positions.borrow_mut().insert(tick, position);
+        self.moved.set(false);
     }
 }
 
 impl System<Event> for MovementSystem {
     fn event(&self, world: &World, event: &Event) {
+        self.track(world);
         match event {
             Event::Recieved(message) => match message {
                 Clientbound::Spawn(client_id, position) => self.spawn(world, *client_id, *position),
@@ -242,6 +247,7 @@ impl System<Event> for MovementSystem {
     }
 
     fn tick(&self, world: &World) {
+        self.track(world);
         Self::update_buffered_positions(world);
     }
 }
@@ -251,5 +257,6 @@ pub fn add(world: World) -> World {
         .register_unsaved::<OtherPlayer>()
         .with_system(MovementSystem {
             positions: RefCell::new(HashMap::new()),
+            moved: Cell::new(true),
         })
 }
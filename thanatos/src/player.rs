@@ -1,5 +1,5 @@
 use crate::{
-    camera::Camera, renderer::RenderObject, transform::Transform, window::Keyboard, Clock, World,
+    camera::Camera, renderer::Renderable, transform::Transform, window::Keyboard, Time, World,
 };
 use glam::{Quat, Vec3};
 use serde::{Deserialize, Serialize};
@@ -18,46 +18,45 @@ impl Default for Health {
 
 #[derive(Archetype, Clone, Serialize, Deserialize)]
 pub struct Player {
-    pub render: RenderObject,
-    pub transform: Transform,
+    #[archetype(flatten)]
+    #[serde(flatten)]
+    pub renderable: Renderable,
     #[serde(skip)]
     pub health: Health,
 }
 
 impl Player {
-    pub fn death(world: &World) {
-        let (mut health, mut transform, _) =
-            world.query_one::<(&mut Health, &mut Transform, Is<Player>)>();
-
+    pub fn death(
+        Single((mut health, mut transform, _)): Single<(&mut Health, &mut Transform, Is<Player>)>,
+    ) {
         if health.0 < 0.0 {
             transform.translation = Vec3::ZERO;
             health.0 = 100.0;
         }
     }
 
-    pub fn tick(world: &World) {
-        let keyboard = world.get::<Keyboard>().unwrap();
-        let mut camera = world.get_mut::<Camera>().unwrap();
-        let clock = world.get::<Clock>().unwrap();
-
-        let (mut transform, _) = world.query_one::<(&mut Transform, Is<Player>)>();
-
+    pub fn tick(
+        keyboard: Res<Keyboard>,
+        mut camera: ResMut<Camera>,
+        time: Res<Time>,
+        Single((mut transform, _)): Single<(&mut Transform, Is<Player>)>,
+    ) {
         let rotation = Quat::from_rotation_y(camera.theta);
 
         if keyboard.is_down("w") {
-            transform.translation += rotation * Vec3::Z * SPEED * clock.delta.as_secs_f32();
+            transform.translation += rotation * Vec3::Z * SPEED * time.delta().as_secs_f32();
         }
 
         if keyboard.is_down("s") {
-            transform.translation -= rotation * Vec3::Z * SPEED * clock.delta.as_secs_f32();
+            transform.translation -= rotation * Vec3::Z * SPEED * time.delta().as_secs_f32();
         }
 
         if keyboard.is_down("a") {
-            transform.translation += rotation * Vec3::X * SPEED * clock.delta.as_secs_f32();
+            transform.translation += rotation * Vec3::X * SPEED * time.delta().as_secs_f32();
         }
 
         if keyboard.is_down("d") {
-            transform.translation -= rotation * Vec3::X * SPEED * clock.delta.as_secs_f32();
+            transform.translation -= rotation * Vec3::X * SPEED * time.delta().as_secs_f32();
         }
 
         camera.target = transform.translation;
@@ -65,5 +64,9 @@ impl Player {
 }
 
 pub fn add(world: World) -> World {
-    world.with_ticker(Player::tick).with_ticker(Player::death)
+    world
+        .with_fn_system(Player::tick)
+        .label("player")
+        .with_fn_system(Player::death)
+        .after("player")
 }
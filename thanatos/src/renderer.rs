@@ -4,7 +4,7 @@ use crate::{
     assets::{Material, MeshCache, MeshId},
     camera::Camera,
     event::Event,
-    transform::Transform,
+    transform::{GlobalTransform, Transform},
     window::{Mouse, Window},
     World,
 };
@@ -27,7 +27,7 @@ use hephaestus::{
 use log::info;
 use serde::{Deserialize, Serialize};
 use styx::{Element, Font, FontSettings, Signals};
-use tecs::EntityId;
+use tecs::{prelude::*, Columns, EntityId, Stage};
 use winit::event::MouseButton;
 
 #[repr(C)]
@@ -62,6 +62,14 @@ pub struct RenderObject {
     pub material: Material,
 }
 
+/// Everything needed to draw an entity, flattened into archetypes with
+/// `#[archetype(flatten)]` and `#[serde(flatten)]`
+#[derive(Bundle, Clone, Serialize, Deserialize)]
+pub struct Renderable {
+    pub render: RenderObject,
+    pub transform: Transform,
+}
+
 #[derive(Clone, Copy)]
 pub enum Anchor {
     TopLeft,
@@ -165,9 +173,86 @@ pub struct Renderer {
     object_layout: Rc<descriptor::Layout>,
     images: Vec<(Rc<Image>, Rc<Image>)>,
     views: Vec<(Rc<ImageView>, Rc<ImageView>)>,
+    geometry: Option<Rc<Geometry>>,
     pub ctx: Context,
 }
 
+/// Buffers built from the render objects, kept between frames and only
+/// rebuilt when a render object is added, changed or removed
+struct Geometry {
+    /// The entities drawn, in the order of their draws
+    entities: Vec<EntityId>,
+    vertices: Rc<Static>,
+    indices: Rc<Static>,
+    draws: Rc<Static>,
+    count: u32,
+    materials: Rc<Static>,
+}
+
+impl Geometry {
+    fn new(
+        ctx: &Context,
+        meshes: &mut MeshCache,
+        entities: Vec<EntityId>,
+        render_objects: &Columns<RenderObject>,
+    ) -> Self {
+        let materials = render_objects
+            .iter()
+            .map(|object| object.material)
+            .collect::<Vec<Material>>();
+        let material_buffer = Static::new(
+            ctx,
+            bytemuck::cast_slice::<Material, u8>(&materials),
+            BufferUsageFlags::STORAGE_BUFFER,
+        )
+        .unwrap();
+
+        let (vertices, indices) = render_objects.iter().fold(
+            (Vec::new(), Vec::new()),
+            |(mut vertices, mut indices), object| {
+                let mesh = meshes.load(&object.mesh).unwrap();
+                vertices.extend_from_slice(&mesh.vertices);
+                indices.extend_from_slice(&mesh.indices);
+                (vertices, indices)
+            },
+        );
+
+        let mut index_offset = 0;
+        let mut vertex_offset = 0;
+
+        let draws = render_objects.iter().flat_map(|object| {
+            let mesh = meshes.load(&object.mesh).unwrap();
+            let draw = [mesh.indices.len() as u32, 1, index_offset, vertex_offset, 0];
+            index_offset += mesh.indices.len() as u32;
+            vertex_offset += mesh.vertices.len() as u32;
+            draw
+        }).collect::<Vec<u32>>();
+        let draw_buffer = Static::new(ctx, bytemuck::cast_slice::<u32, u8>(&draws), BufferUsageFlags::INDIRECT_BUFFER).unwrap();
+
+        let vertex_buffer = Static::new(
+            ctx,
+            bytemuck::cast_slice::<Vertex, u8>(&vertices),
+            BufferUsageFlags::VERTEX_BUFFER,
+        )
+        .unwrap();
+        let index_buffer = Static::new(
+            ctx,
+            bytemuck::cast_slice::<u32, u8>(&indices),
+            BufferUsageFlags::INDEX_BUFFER,
+        )
+        .unwrap();
+
+        Self {
+            entities,
+            vertices: vertex_buffer,
+            indices: index_buffer,
+            draws: draw_buffer,
+            count: draws.len() as u32 / 5,
+            materials: material_buffer,
+        }
+    }
+}
+
 impl Renderer {
     pub const FRAMES_IN_FLIGHT: usize = 3;
 
@@ -285,6 +370,7 @@ impl Renderer {
             object_layout,
             images,
             views,
+            geometry: None,
         })
     }
 
@@ -294,6 +380,7 @@ impl Renderer {
                 .with_resource(self)
                 .with_resource(Ui::new())
                 .with_ticker(Self::draw)
+                .in_stage(Stage::Render)
                 .with_handler(Ui::event)
         }
     }
@@ -441,18 +528,20 @@ impl Renderer {
 
         let clear_values = [clear_colour([0.0, 0.0, 0.0, 1.0]), clear_depth(1.0)];
 
-        let mut meshes = world.get_mut::<MeshCache>().unwrap();
-        let (entities, render_objects) = world.query::<(EntityId, &RenderObject)>();
+        let (entities, render_objects, changed) =
+            world.query::<(EntityId, &RenderObject, Changed<RenderObject>)>();
+        let ids = entities.iter().copied().collect::<Vec<EntityId>>();
 
-        let transforms = entities
+        let transforms = ids
             .iter()
-            .map(|id| {
-                world
+            .map(|id| match world.get_component::<GlobalTransform>(*id) {
+                Some(global) => global.0,
+                None => world
                     .get_component::<Transform>(*id)
-                    .map(|x| *x)
-                    .unwrap_or_default()
+                    .map(|x| x.matrix())
+                    .unwrap_or_default(),
             })
-            .flat_map(|transform| transform.matrix().to_cols_array())
+            .flat_map(|matrix| matrix.to_cols_array())
             .collect::<Vec<f32>>();
         let transform_buffer = Static::new(
             &renderer.ctx,
@@ -461,60 +550,27 @@ impl Renderer {
         )
         .unwrap();
 
-        let materials = render_objects
-            .iter()
-            .map(|object| object.material)
-            .collect::<Vec<Material>>();
-        let material_buffer = Static::new(
-            &renderer.ctx,
-            bytemuck::cast_slice::<Material, u8>(&materials),
-            BufferUsageFlags::STORAGE_BUFFER,
-        )
-        .unwrap();
+        let stale = changed.iter().any(|changed| changed)
+            || renderer
+                .geometry
+                .as_ref()
+                .is_none_or(|geometry| geometry.entities != ids);
+        if stale {
+            let mut meshes = world.get_mut::<MeshCache>().unwrap();
+            let geometry = Geometry::new(&renderer.ctx, &mut meshes, ids, &render_objects);
+            renderer.geometry = Some(Rc::new(geometry));
+        }
+        drop((entities, render_objects, changed));
+        let geometry = renderer.geometry.clone().unwrap();
 
         let set = renderer
             .object_layout
             .alloc()
             .unwrap()
             .write_buffer(0, &transform_buffer)
-            .write_buffer(1, &material_buffer)
+            .write_buffer(1, &geometry.materials)
             .finish();
 
-        let (vertices, indices) = render_objects.iter().fold(
-            (Vec::new(), Vec::new()),
-            |(mut vertices, mut indices), object| {
-                let mesh = meshes.load(&object.mesh).unwrap();
-                vertices.extend_from_slice(&mesh.vertices);
-                indices.extend_from_slice(&mesh.indices);
-                (vertices, indices)
-            },
-        );
-
-        let mut index_offset = 0;
-        let mut vertex_offset = 0;
-
-        let draws = render_objects.iter().flat_map(|object| {
-            let mesh = meshes.load(&object.mesh).unwrap();
-            let draw = [mesh.indices.len() as u32, 1, index_offset, vertex_offset, 0];
-            index_offset += mesh.indices.len() as u32;
-            vertex_offset += mesh.vertices.len() as u32;
-            draw
-        }).collect::<Vec<u32>>();
-        let draw_buffer = Static::new(&renderer.ctx, bytemuck::cast_slice::<u32, u8>(&draws), BufferUsageFlags::INDIRECT_BUFFER).unwrap();
-
-        let vertex_buffer = Static::new(
-            &renderer.ctx,
-            bytemuck::cast_slice::<Vertex, u8>(&vertices),
-            BufferUsageFlags::VERTEX_BUFFER,
-        )
-        .unwrap();
-        let index_buffer = Static::new(
-            &renderer.ctx,
-            bytemuck::cast_slice::<u32, u8>(&indices),
-            BufferUsageFlags::INDEX_BUFFER,
-        )
-        .unwrap();
-
         let scene = world.get_mut::<Ui>().unwrap().paint(&world);
         let frame = if !scene.is_empty() {
             Some(
@@ -548,8 +604,8 @@ impl Renderer {
             .set_scissor(size.width, size.height)
             .bind_descriptor_set(&camera_set, 0)
             .bind_descriptor_set(&set, 1)
-            .bind_vertex_buffer(&vertex_buffer, 0)
-            .bind_index_buffer(&index_buffer).draw_indexed_indirect(&draw_buffer, 0, draws.len() as u32 / 5, 20);
+            .bind_vertex_buffer(&geometry.vertices, 0)
+            .bind_index_buffer(&geometry.indices).draw_indexed_indirect(&geometry.draws, 0, geometry.count, 20);
 
         let cmd = match frame {
             Some(frame) => renderer.ui.draw(frame, cmd),
@@ -1,5 +1,8 @@
 use glam::{Mat4, Quat, Vec3};
 use serde::{Deserialize, Serialize};
+use tecs::{EntityId, Parent, Stage, Without};
+
+use crate::World;
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Transform {
@@ -29,3 +32,104 @@ impl Default for Transform {
         Self::IDENTITY
     }
 }
+
+/// World space transform of an entity, computed from its [`Transform`] and
+/// those of its ancestors by [`propagate`] each tick.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GlobalTransform(pub Mat4);
+
+/// Walks down the hierarchy from every root, entities without a
+/// [`Transform`] pass their parent's transform on unchanged.
+pub fn propagate(world: &World) {
+    let (entities, transforms, _) =
+        world.query::<(EntityId, Option<&Transform>, Without<Parent>)>();
+    let mut stack = entities
+        .iter()
+        .zip(transforms.iter())
+        .map(|(id, transform)| (*id, local(transform)))
+        .collect::<Vec<_>>();
+    drop((entities, transforms));
+
+    while let Some((id, matrix)) = stack.pop() {
+        // Moving the entity to a new table only happens the first time
+        match world.get_component_mut::<GlobalTransform>(id) {
+            Some(mut global) => global.0 = matrix,
+            None => {
+                let _ = world.insert_component(id, GlobalTransform(matrix));
+            }
+        }
+        stack.extend(world.children(id).into_iter().map(|child| {
+            let transform = world.get_component::<Transform>(child);
+            (child, matrix * local(transform.as_deref()))
+        }));
+    }
+}
+
+fn local(transform: Option<&Transform>) -> Mat4 {
+    transform
+        .map(|transform| transform.matrix())
+        .unwrap_or(Mat4::IDENTITY)
+}
+
+pub fn add(world: World) -> World {
+    world.with_ticker(propagate).in_stage(Stage::PostUpdate)
+}
+
+#[cfg(test)]
+mod tests {
+    use glam::{Mat4, Vec3};
+    use tecs::prelude::*;
+
+    use super::{propagate, GlobalTransform, Transform};
+    use crate::World;
+
+    #[derive(Archetype, Clone)]
+    struct Group {
+        id: u32,
+    }
+
+    #[derive(Archetype, Clone)]
+    struct Node {
+        transform: Transform,
+    }
+
+    fn global(world: &World, entity: EntityId) -> Mat4 {
+        world.get_component::<GlobalTransform>(entity).unwrap().0
+    }
+
+    #[test]
+    pub fn test_propagate() {
+        let world = World::new()
+            .register_unsaved::<Group>()
+            .register_unsaved::<Node>();
+        let node = |x| Node {
+            transform: Transform {
+                translation: Vec3::X * x,
+                ..Transform::IDENTITY
+            },
+        };
+        let root = world.spawn(Group { id: 0 });
+        let child = world.spawn(node(1.0));
+        let grandchild = world.spawn(node(2.0));
+        world.set_parent(child, root).unwrap();
+        world.set_parent(grandchild, child).unwrap();
+
+        propagate(&world);
+        assert_eq!(global(&world, root), Mat4::IDENTITY);
+        assert_eq!(global(&world, child), Mat4::from_translation(Vec3::X));
+        assert_eq!(
+            global(&world, grandchild),
+            Mat4::from_translation(Vec3::X * 3.0)
+        );
+
+        // Losing the transform passes the parent's on instead of keeping the
+        // old one
+        world.remove_component::<Transform>(child).unwrap();
+        propagate(&world);
+        assert_eq!(global(&world, child), Mat4::IDENTITY);
+        assert_eq!(
+            global(&world, grandchild),
+            Mat4::from_translation(Vec3::X * 2.0)
+        );
+    }
+}
@@ -14,6 +14,7 @@ use winit::{
 };
 
 use crate::{event::Event, World};
+use tecs::Stage;
 
 #[derive(Clone, Default)]
 pub struct Mouse {
@@ -176,7 +177,11 @@ impl Window {
                 .with_resource(Mouse::default())
                 .with_resource(Keyboard::default())
                 .with_ticker(Mouse::tick)
+                .in_stage(Stage::PreUpdate)
+                .label("input")
                 .with_ticker(Self::tick)
+                .in_stage(Stage::PreUpdate)
+                .label("input")
         }
     }
 